@@ -1,24 +1,60 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
 use color_eyre::eyre::Result;
 use crossterm::event::{self};
-use ratatui::{DefaultTerminal, Frame};
+use ratatui::{DefaultTerminal, Frame, layout::Rect};
 use tui_input::Input;
 
 use crate::{
+    browser::Browser,
+    diagnostics::DiagnosticsContainer,
+    diff::Diff,
+    events::summarize,
+    io::{Progress, extract_item, free_path},
+    journal::{Journal, Operation},
     list::ListContainer,
+    preview::Preview,
+    restore::RestoreQueue,
+    trash_entry::TrashEntry,
     ui::{
-        Message, layout, render_choice_popup, render_empty_list, render_footer, render_list,
-        render_message, render_scrollbar, render_search_input,
+        Message, layout, preview_layout, render_browser, render_choice_popup,
+        render_conflict_popup, render_diagnostics, render_diff, render_empty_list, render_footer,
+        render_history, render_list, render_message, render_path_input, render_preview,
+        render_progress_popup, render_scrollbar, render_search_input,
     },
-    utils::{Choice, Mode, SortMode, compute_list_size, with_search},
+    utils::{Choice, Mode, PathAction, SortMode, compute_list_size, visible_rows},
 };
 
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct App {
     pub input: Input,
+    pub path_input: Input,
     pub mode: Mode,
     pub sort_mode: SortMode,
     pub choice_popup: Option<Choice>,
     pub message: Option<Message>,
     pub list_container: ListContainer,
+    pub diagnostics: DiagnosticsContainer,
+    pub journal: Journal,
+    /// A restore waiting for a conflict to be resolved
+    pub restore_queue: Option<RestoreQueue>,
+    /// Items to copy out of the trash and the directory to copy them to
+    pub pending_extract: Option<(Vec<TrashEntry>, PathBuf)>,
+    /// Name and progress of the item being copied to another filesystem
+    pub progress: Option<(String, Progress)>,
+    /// The diff shown over everything else and how far it's scrolled
+    pub diff: Option<(Diff, u16)>,
+    pub show_preview: bool,
+    /// Show the directory items came from next to their name
+    pub show_location: bool,
+    /// The trashed directory being browsed, instead of the list
+    pub browser: Option<Browser>,
+    /// The preview of the selected item, read when it's first shown
+    preview: Option<(PathBuf, Preview)>,
 }
 
 impl App {
@@ -28,17 +64,42 @@ impl App {
         App {
             mode: Mode::ListView,
             input: Input::default(),
+            path_input: Input::default(),
             list_container: ListContainer::new(compute_list_size(terminal), &default_sorting),
+            diagnostics: DiagnosticsContainer::new(),
             message: None,
             choice_popup: None,
             sort_mode: default_sorting,
+            journal: Journal::load(),
+            restore_queue: None,
+            pending_extract: None,
+            progress: None,
+            diff: None,
+            show_preview: false,
+            show_location: false,
+            browser: None,
+            preview: None,
         }
     }
 
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
         loop {
+            self.list_container.receive_sizes(&self.sort_mode);
+            if let Some((diff, _)) = &mut self.diff {
+                diff.receive();
+            }
             terminal.draw(|frame| self.draw(frame))?;
 
+            // Sizes show up as they're measured, diffs once they're ready
+            let is_waiting = self.list_container.is_measuring()
+                || self
+                    .diff
+                    .as_ref()
+                    .is_some_and(|(diff, _)| diff.is_pending());
+            if is_waiting && !event::poll(PROGRESS_INTERVAL)? {
+                continue;
+            }
+
             let event = event::read()?;
 
             if event.is_resize() {
@@ -52,44 +113,258 @@ impl App {
                     _ => {}
                 }
             }
+
+            if !matches!(self.mode, Mode::PathInput(PathAction::RenameRestore)) {
+                self.run_restore(terminal);
+            }
+
+            self.run_extract(terminal);
+        }
+    }
+
+    /// Restores what's left in the queue, unless another conflict comes up.
+    /// The screen is redrawn while items are copied between filesystems.
+    fn run_restore(&mut self, terminal: &mut DefaultTerminal) {
+        let Some(mut queue) = self.restore_queue.take() else {
+            return;
+        };
+
+        let mut last_draw = Instant::now();
+        let is_done = queue
+            .run(&mut |entry, progress| {
+                self.draw_progress(terminal, &mut last_draw, entry, progress)
+            })
+            .is_none();
+        self.progress = None;
+
+        if !is_done {
+            self.restore_queue = Some(queue);
+            return;
+        }
+
+        let mut message = match (queue.results.len(), queue.skipped) {
+            (0, skipped) => Message::info(format!("{} items skipped", skipped)),
+            _ => summarize(&queue.results, "restored"),
+        };
+        if queue.skipped > 0 && !queue.results.is_empty() {
+            message
+                .text
+                .push_str(&format!(", {} skipped", queue.skipped));
+        }
+
+        self.journal.push(
+            queue
+                .restored
+                .into_iter()
+                .map(|(entry, target)| Operation::Restored { entry, target })
+                .collect(),
+        );
+        self.message = Some(message);
+        self.list_container.refresh(&self.sort_mode);
+        if let Some(browser) = &mut self.browser {
+            browser.refresh();
+        }
+    }
+
+    /// What restores and extracts act on: the item selected in the browser,
+    /// otherwise the marked or selected items of the list.
+    pub fn get_target_items(&self) -> Vec<TrashEntry> {
+        match &self.browser {
+            Some(browser) => browser.get_selected_item().into_iter().collect(),
+            None => self.list_container.get_target_items(),
+        }
+    }
+
+    /// Everything in the trash that a restore may need as a parent. Not the
+    /// browsed directory though, only a part of it is being restored.
+    pub fn get_trashed_items(&self) -> Vec<TrashEntry> {
+        self.list_container
+            .items
+            .iter()
+            .filter(|item| {
+                self.browser
+                    .as_ref()
+                    .is_none_or(|browser| browser.entry.info_path != item.info_path)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Copies the items waiting to be extracted, name collisions get a
+    /// suffix instead of a conflict dialog.
+    fn run_extract(&mut self, terminal: &mut DefaultTerminal) {
+        let Some((items, dir)) = self.pending_extract.take() else {
+            return;
+        };
+
+        let mut last_draw = Instant::now();
+        let results = items
+            .into_iter()
+            .map(|item| {
+                let target = free_path(&dir.join(&item.display_name));
+                let result = extract_item(&item, &target, &mut |progress| {
+                    self.draw_progress(terminal, &mut last_draw, &item, progress)
+                });
+                (item, result)
+            })
+            .collect::<Vec<_>>();
+        self.progress = None;
+
+        self.message = Some(summarize(&results, "extracted"));
+    }
+
+    fn draw_progress(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        last_draw: &mut Instant,
+        entry: &TrashEntry,
+        progress: Progress,
+    ) {
+        if last_draw.elapsed() >= PROGRESS_INTERVAL {
+            self.progress = Some((entry.display_name.to_string_lossy().to_string(), progress));
+            let _ = terminal.draw(|frame| self.draw(frame));
+            *last_draw = Instant::now();
         }
     }
 
     fn draw(&mut self, frame: &mut Frame) {
         let [input_area, list_area, bottom_area] = layout(&self.mode).areas(frame.area());
 
-        render_footer(frame, bottom_area, &self.mode);
+        render_footer(frame, bottom_area, &self.mode, self.browser.is_some());
 
-        if self.list_container.items.is_empty() {
-            render_empty_list(frame, list_area);
+        if matches!(
+            self.mode,
+            Mode::Diagnostics | Mode::PathInput(PathAction::Adopt)
+        ) {
+            render_diagnostics(frame, list_area, &mut self.diagnostics);
         } else {
-            render_list(
-                frame,
-                list_area,
-                with_search(&self.list_container.items, self.input.value()),
-                &mut self.list_container.state,
-            );
+            self.draw_list(frame, list_area);
         }
-        render_scrollbar(frame, list_area, &self.list_container);
 
         if matches!(self.mode, Mode::Filtering) {
-            render_search_input(frame, input_area, &self.input)
+            render_search_input(
+                frame,
+                input_area,
+                &self.input,
+                self.list_container.filter_error.as_deref(),
+            )
+        }
+
+        if let Some(queue) = &self.restore_queue
+            && let Some(job) = queue.conflict()
+            && !matches!(self.mode, Mode::PathInput(PathAction::RenameRestore))
+        {
+            render_conflict_popup(frame, job, queue.apply_to_all, queue.remaining());
+        }
+
+        if let Mode::PathInput(action) = &self.mode {
+            render_path_input(
+                frame,
+                match action {
+                    PathAction::Adopt => "Original location",
+                    PathAction::RenameRestore => "Restore as",
+                    PathAction::RestoreTo => "Restore to directory",
+                    PathAction::Extract => "Extract a copy to directory",
+                },
+                &self.path_input,
+            );
         }
 
         if let Some(choice) = &self.choice_popup {
+            let marked = match self.browser {
+                Some(_) => 0,
+                None => self.list_container.marked.len(),
+            };
+            // A selected group stands for all its items
+            let targets = self.get_target_items().len();
+
             render_choice_popup(
                 frame,
-                match choice {
-                    Choice::Restore => "Restore selected item?",
-                    Choice::Delete => "Delete selected item?",
-                    Choice::Empty => "Empty the trash?",
-                    Choice::Override => "Override existing file?",
+                &match choice {
+                    Choice::Restore if marked > 0 => format!("Restore {} marked items?", marked),
+                    Choice::Restore if targets > 1 => format!("Restore {} items?", targets),
+                    Choice::Restore => "Restore selected item?".to_string(),
+                    Choice::Delete if marked > 0 => format!("Delete {} marked items?", marked),
+                    Choice::Delete if targets > 1 => format!("Delete {} items?", targets),
+                    Choice::Delete => "Delete selected item?".to_string(),
+                    Choice::Empty => "Empty the trash?".to_string(),
+                    Choice::DeleteBroken => "Delete broken item?".to_string(),
+                    Choice::Purge => "Purge deleted items now?".to_string(),
                 },
             );
         }
 
+        if matches!(self.mode, Mode::History) {
+            render_history(frame, &mut self.journal);
+        }
+
+        if let Some((diff, scroll)) = &mut self.diff {
+            render_diff(frame, diff, scroll);
+        }
+
+        if let Some((name, progress)) = &self.progress {
+            render_progress_popup(frame, name, progress);
+        }
+
         if let Some(message) = &self.message {
             render_message(frame, message);
         }
     }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let [area, preview_area] = preview_layout(self.show_preview).areas(area);
+        if self.show_preview {
+            self.draw_preview(frame, preview_area);
+        }
+
+        if let Some(browser) = &mut self.browser {
+            render_browser(frame, area, browser);
+            return;
+        }
+
+        if self.list_container.items.is_empty() {
+            render_empty_list(frame, area);
+        } else {
+            let selected_location = self
+                .list_container
+                .get_slected_item()
+                .map(|item| item.restore_location.clone());
+
+            render_list(
+                frame,
+                area,
+                visible_rows(&self.list_container, self.show_location),
+                &mut self.list_container.state,
+                selected_location.as_deref(),
+                self.show_location,
+            );
+        }
+
+        render_scrollbar(frame, area, &self.list_container);
+    }
+
+    fn draw_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let selected = match &self.browser {
+            Some(browser) => browser.get_selected_item(),
+            None => self.list_container.get_slected_item().cloned(),
+        };
+        let Some(content_path) = selected.map(|item| item.content_path) else {
+            render_preview(frame, area, None);
+            return;
+        };
+
+        if self
+            .preview
+            .as_ref()
+            .is_none_or(|(path, _)| *path != content_path)
+        {
+            self.preview = Some((content_path.clone(), Preview::read(&content_path)));
+        }
+
+        render_preview(
+            frame,
+            area,
+            self.preview.as_ref().map(|(_, preview)| preview),
+        );
+    }
 }
@@ -0,0 +1,230 @@
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ratatui::widgets::TableState;
+
+use crate::trash_entry::TrashEntry;
+
+/// Navigates the content of a trashed directory, so single items can be
+/// restored out of it.
+pub struct Browser {
+    /// The trashed directory
+    pub entry: TrashEntry,
+    /// The directory shown, relative to the trashed one
+    pub dir: PathBuf,
+    /// Names of the children, directories first
+    pub children: Vec<(OsString, bool)>,
+    pub state: TableState,
+}
+
+impl Browser {
+    /// Only a real directory can be browsed. A trashed symlink to one points
+    /// at files that aren't in the trash.
+    pub fn can_browse(entry: &TrashEntry) -> bool {
+        is_real_dir(&entry.content_path)
+    }
+
+    pub fn new(entry: TrashEntry) -> Self {
+        let mut browser = Browser {
+            entry,
+            dir: PathBuf::new(),
+            children: Vec::new(),
+            state: TableState::default(),
+        };
+
+        browser.refresh();
+
+        browser
+    }
+
+    /// Lists the directory again, restored children disappear. When the
+    /// directory itself is gone, its closest remaining parent is shown.
+    pub fn refresh(&mut self) {
+        while !self.is_at_top() && !is_real_dir(&self.shown_dir()) {
+            self.dir.pop();
+        }
+
+        self.children = Some(self.shown_dir())
+            .filter(|dir| is_real_dir(dir))
+            .and_then(|dir| fs::read_dir(dir).ok())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| {
+                        let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                        (entry.file_name(), is_dir)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.children
+            .sort_by(|(a, a_is_dir), (b, b_is_dir)| b_is_dir.cmp(a_is_dir).then(a.cmp(b)));
+
+        match self.state.selected() {
+            _ if self.children.is_empty() => self.state.select(None),
+            Some(selected) => self
+                .state
+                .select(Some(selected.min(self.children.len() - 1))),
+            None => self.state.select_first(),
+        }
+    }
+
+    // The directory shown, inside the trash. Joining an empty path would add
+    // a trailing slash, which makes a symlink look like its target.
+    fn shown_dir(&self) -> PathBuf {
+        match self.is_at_top() {
+            true => self.entry.content_path.clone(),
+            false => self.entry.content_path.join(&self.dir),
+        }
+    }
+
+    /// Opens the selected child if it's a directory
+    pub fn enter(&mut self) {
+        if let Some((name, true)) = self.state.selected().and_then(|i| self.children.get(i)) {
+            self.dir.push(name);
+            self.state.select(None);
+            self.refresh();
+        }
+    }
+
+    pub fn is_at_top(&self) -> bool {
+        self.dir.as_os_str().is_empty()
+    }
+
+    /// Goes to the parent directory
+    pub fn leave(&mut self) {
+        let Some(name) = self.dir.file_name().map(|name| name.to_os_string()) else {
+            return;
+        };
+
+        self.dir.pop();
+        self.refresh();
+        // Back on the directory we came from
+        let position = self.children.iter().position(|(child, _)| *child == name);
+        self.state.select(position.or(self.state.selected()));
+    }
+
+    pub fn next(&mut self) {
+        if !self.children.is_empty() {
+            self.state.select(Some(
+                self.state
+                    .selected()
+                    .map_or(0, |i| (i + 1) % self.children.len()),
+            ));
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if !self.children.is_empty() {
+            self.state.select(Some(match self.state.selected() {
+                Some(0) | None => self.children.len() - 1,
+                Some(i) => i - 1,
+            }));
+        }
+    }
+
+    /// The selected child as an item of its own, restored to the same place
+    /// inside the directory's original location.
+    pub fn get_selected_item(&self) -> Option<TrashEntry> {
+        self.state
+            .selected()
+            .and_then(|i| self.children.get(i))
+            .map(|(name, _)| self.entry.child(&self.dir.join(name)))
+    }
+
+    /// The directory shown, as it would be restored
+    pub fn location(&self) -> PathBuf {
+        self.entry.restore_location.join(&self.dir)
+    }
+}
+
+fn is_real_dir(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::{
+        io::{restore_item_to, retrash_item},
+        sizes::remember_size,
+        test_utils::{TempDir, trash_dir, trash_entry},
+    };
+
+    #[test]
+    fn can_restore_child() {
+        let root = TempDir::new("browser");
+        let trash_dir = trash_dir(&root.join("trash"));
+        fs::create_dir_all(trash_dir.files.join("project/src")).unwrap();
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        fs::write(trash_dir.files.join("project/src/main.rs"), "fn main() {}").unwrap();
+        fs::write(trash_dir.files.join("project/Cargo.toml"), "").unwrap();
+        fs::write(trash_dir.info_path_for("project".as_ref()), "").unwrap();
+
+        let mut browser = Browser::new(trash_entry(&trash_dir, "project", &root.join("project")));
+
+        // Directories come first
+        browser.enter();
+        let child = browser.get_selected_item().unwrap();
+        assert_eq!(child.restore_location, root.join("project/src/main.rs"));
+
+        restore_item_to(&child, &child.restore_location, &mut |_| {}).unwrap();
+        browser.refresh();
+
+        assert!(browser.children.is_empty());
+        assert!(root.join("project/src/main.rs").exists());
+        // The rest stays in the trash
+        assert!(browser.entry.info_path.exists());
+        browser.leave();
+        assert_eq!(browser.get_selected_item().unwrap().display_name, "src");
+
+        // Undoing puts it back inside the directory, not next to it
+        remember_size(&trash_dir, "project".as_ref(), 42);
+        assert!(
+            fs::read_to_string(&trash_dir.directory_sizes)
+                .unwrap()
+                .contains("project")
+        );
+        retrash_item(&child, &child.restore_location).unwrap();
+        assert_eq!(
+            fs::read_to_string(trash_dir.files.join("project/src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert!(!root.join("project/src/main.rs").exists());
+        assert_eq!(fs::read_dir(&trash_dir.info).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(&trash_dir.files).unwrap().count(), 1);
+        assert!(
+            !fs::read_to_string(&trash_dir.directory_sizes)
+                .unwrap_or_default()
+                .contains("project")
+        );
+
+        // Once the directory is gone it gets an entry of its own
+        restore_item_to(&child, &child.restore_location, &mut |_| {}).unwrap();
+        fs::remove_dir_all(trash_dir.files.join("project")).unwrap();
+        fs::remove_file(&browser.entry.info_path).unwrap();
+        retrash_item(&child, &child.restore_location).unwrap();
+        assert!(trash_dir.files.join("main.rs").exists());
+        assert!(trash_dir.info_path_for("main.rs".as_ref()).exists());
+    }
+
+    #[test]
+    fn cannot_browse_symlink_to_dir() {
+        let root = TempDir::new("browser-link");
+        let trash_dir = trash_dir(&root.join("trash"));
+        fs::create_dir_all(root.join("important")).unwrap();
+        fs::write(root.join("important/data.txt"), "").unwrap();
+        symlink(root.join("important"), trash_dir.files.join("link")).unwrap();
+
+        let link = trash_entry(&trash_dir, "link", &root.join("link"));
+        assert!(!Browser::can_browse(&link));
+        let browser = Browser::new(link);
+        assert!(browser.children.is_empty());
+        assert!(browser.get_selected_item().is_none());
+    }
+}
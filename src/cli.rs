@@ -0,0 +1,608 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io::{self, ErrorKind, IsTerminal, Write, stderr, stdout},
+    os::unix::ffi::OsStrExt,
+    path::{self, Path, PathBuf},
+    process::ExitCode,
+};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::{
+    io::{
+        Progress, empty_bin, extract_item, list_trash_entries, purge_expired, put_item,
+        restore_item_to, stage_item,
+    },
+    sizes::measure_sizes,
+    trash_entry::TrashEntry,
+    trash_info::encode_path,
+    utils::{grace_period, parse_duration},
+};
+
+const USAGE: &str = "\
+Usage: trash-tui [COMMAND]
+
+Without a command the interactive UI is started.
+
+Commands:
+  put <PATH>...                 Move files and directories to the trash
+  list [--json|--ndjson]        List the trashed items, oldest first
+  restore <NAME|PATH> [--to <DIR>]
+                                Restore an item to its original location, or
+                                into DIR
+  extract <NAME|PATH> [--to <DIR>]
+                                Copy an item out of the trash into DIR, or the
+                                current directory, and keep it in the trash
+  rm <NAME>...                  Permanently delete items from the trash
+  empty [--older-than <AGE>]    Permanently delete everything, or only items
+                                trashed more than AGE ago (e.g. 30d, 12h, 2w)
+  purge [--expired]             Get rid of deleted items right away, or only
+                                those past their grace period
+  help                          Show this message
+
+NAME is the name of the trashed file or its name inside the trash directory
+(e.g. video_2.avi), PATH is its original location.
+
+Deleted items are kept for a grace period, a day unless TRASH_TUI_GRACE_PERIOD
+says otherwise (e.g. 12h), and can be brought back from the history of the
+interactive UI until then. The UI, rm and empty purge the expired ones.
+
+Exit codes: 0 - success, 1 - an operation failed, 2 - invalid usage,
+3 - no matching item, 4 - several items match";
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_NOT_FOUND: u8 = 3;
+const EXIT_AMBIGUOUS: u8 = 4;
+
+/// Runs a non-interactive command, `args` excludes the program name.
+pub fn run(args: &[OsString]) -> ExitCode {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.to_string_lossy(), args),
+        None => return usage_error("Missing command"),
+    };
+
+    match command.as_ref() {
+        "put" => put(args),
+        "list" => list(args),
+        "restore" => restore(args),
+        "extract" => extract(args),
+        "rm" => rm(args),
+        "empty" => empty(args),
+        "purge" => purge(args),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => usage_error(&format!("Unknown command: {}", command)),
+    }
+}
+
+fn put(args: &[OsString]) -> ExitCode {
+    if args.is_empty() {
+        return usage_error("Missing path to put in the trash");
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+
+    for path in args.iter().map(PathBuf::from) {
+        if let Err(e) = put_item(&path) {
+            eprintln!("trash-tui: cannot trash {}: {}", path.display(), e);
+            exit_code = ExitCode::from(EXIT_FAILURE);
+        }
+    }
+
+    exit_code
+}
+
+/// One trashed item in the `list --json` and `list --ndjson` output. The
+/// fields are documented in the README, don't rename or remove them. Paths
+/// come readable and lossy, and percent-encoded byte by byte in the
+/// `_encoded` fields, JSON strings can't hold what isn't UTF-8.
+#[derive(Serialize)]
+struct ListedEntry {
+    name: String,
+    name_encoded: String,
+    original_path: String,
+    original_path_encoded: String,
+    content_path: String,
+    content_path_encoded: String,
+    info_path: String,
+    info_path_encoded: String,
+    deletion_date: String,
+    size: Option<u64>,
+    trash_root: String,
+    trash_root_encoded: String,
+}
+
+impl ListedEntry {
+    fn new(entry: &TrashEntry, sizes: &HashMap<PathBuf, u64>) -> Self {
+        let name = Path::new(&entry.display_name);
+
+        ListedEntry {
+            name: name.to_string_lossy().to_string(),
+            name_encoded: encode_path(name),
+            original_path: entry.restore_location.to_string_lossy().to_string(),
+            original_path_encoded: encode_path(&entry.restore_location),
+            content_path: entry.content_path.to_string_lossy().to_string(),
+            content_path_encoded: encode_path(&entry.content_path),
+            info_path: entry.info_path.to_string_lossy().to_string(),
+            info_path_encoded: encode_path(&entry.info_path),
+            deletion_date: entry.date.to_rfc3339(),
+            size: sizes.get(&entry.content_path).copied(),
+            trash_root: entry.trash_dir.root.to_string_lossy().to_string(),
+            trash_root_encoded: encode_path(&entry.trash_dir.root),
+        }
+    }
+}
+
+fn list(args: &[OsString]) -> ExitCode {
+    let format = match args {
+        [] => None,
+        [flag] if flag == "--json" || flag == "--ndjson" => Some(flag.to_string_lossy()),
+        _ => return usage_error("list only takes --json or --ndjson"),
+    };
+
+    let mut entries = list_trash_entries();
+    entries.sort_by_key(|entry| entry.date);
+    // Sizes are only part of the JSON, the plain list doesn't wait for them
+    let sizes = match format {
+        Some(_) => measure_sizes(&entries),
+        None => HashMap::new(),
+    };
+
+    match write_list(&mut stdout().lock(), &entries, &sizes, format.as_deref()) {
+        // A closed pipe, e.g. `trash-tui list | head`, isn't worth reporting
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+            eprintln!("trash-tui: cannot write the list: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+fn write_list(
+    out: &mut impl Write,
+    entries: &[TrashEntry],
+    sizes: &HashMap<PathBuf, u64>,
+    format: Option<&str>,
+) -> io::Result<()> {
+    match format {
+        Some("--json") => {
+            serde_json::to_writer_pretty(
+                &mut *out,
+                &entries
+                    .iter()
+                    .map(|entry| ListedEntry::new(entry, sizes))
+                    .collect::<Vec<_>>(),
+            )?;
+            out.write_all(b"\n")
+        }
+        Some(_) => entries.iter().try_for_each(|entry| {
+            serde_json::to_writer(&mut *out, &ListedEntry::new(entry, sizes))?;
+            out.write_all(b"\n")
+        }),
+        // Paths are written as raw bytes, they aren't necessarily UTF-8
+        None => entries.iter().try_for_each(|entry| {
+            out.write_all(
+                &[
+                    entry
+                        .date
+                        .format("%Y-%m-%d %H:%M:%S ")
+                        .to_string()
+                        .as_bytes(),
+                    entry.restore_location.as_os_str().as_bytes(),
+                    b"\n",
+                ]
+                .concat(),
+            )
+        }),
+    }
+}
+
+fn restore(args: &[OsString]) -> ExitCode {
+    let (entry, dir) = match find_entry_and_dir(args, "restore") {
+        Ok(found) => found,
+        Err(exit_code) => return exit_code,
+    };
+
+    let target = match dir {
+        Some(dir) => dir.join(&entry.display_name),
+        None => entry.restore_location.clone(),
+    };
+
+    // Only a move to another filesystem reports progress, it's a copy
+    match with_progress(|progress| restore_item_to(&entry, &target, progress)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            eprintln!(
+                "trash-tui: cannot restore {}: the path already exists",
+                target.display()
+            );
+            ExitCode::from(EXIT_FAILURE)
+        }
+        Err(e) => {
+            eprintln!("trash-tui: cannot restore {}: {}", target.display(), e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn purge(args: &[OsString]) -> ExitCode {
+    let grace_period = match args {
+        [] => None,
+        [flag] if flag == "--expired" => Some(grace_period()),
+        _ => return usage_error("purge only takes --expired"),
+    };
+
+    match purge_expired(grace_period) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("trash-tui: cannot purge deleted items: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn extract(args: &[OsString]) -> ExitCode {
+    let (entry, dir) = match find_entry_and_dir(args, "extract") {
+        Ok(found) => found,
+        Err(exit_code) => return exit_code,
+    };
+
+    let target = dir.unwrap_or_default().join(&entry.display_name);
+
+    match with_progress(|progress| extract_item(&entry, &target, progress)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("trash-tui: cannot extract {}: {}", target.display(), e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn find_entry_and_dir(
+    args: &[OsString],
+    command: &str,
+) -> Result<(TrashEntry, Option<PathBuf>), ExitCode> {
+    let (query, dir) = parse_query_and_dir(args, command)?;
+
+    Ok((find_entry(query)?, dir))
+}
+
+/// Parses `<NAME|PATH> [--to <DIR>]`.
+fn parse_query_and_dir<'a>(
+    args: &'a [OsString],
+    command: &str,
+) -> Result<(&'a OsStr, Option<PathBuf>), ExitCode> {
+    let mut query = None;
+    let mut dir = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_bytes() {
+            b"--to" => match args.next() {
+                Some(value) => dir = Some(PathBuf::from(value)),
+                None => return Err(usage_error("--to needs a directory")),
+            },
+            bytes if bytes.starts_with(b"--to=") => {
+                dir = Some(PathBuf::from(OsStr::from_bytes(&bytes[b"--to=".len()..])))
+            }
+            _ if query.is_none() => query = Some(arg.as_os_str()),
+            _ => {
+                return Err(usage_error(&format!(
+                    "{} takes exactly one name or path",
+                    command
+                )));
+            }
+        }
+    }
+
+    let Some(query) = query else {
+        return Err(usage_error(&format!(
+            "{} takes exactly one name or path",
+            command
+        )));
+    };
+
+    Ok((query, dir))
+}
+
+/// Shows the progress of a copy on stderr, when it's a terminal.
+fn with_progress(copy: impl FnOnce(&mut dyn FnMut(Progress)) -> io::Result<()>) -> io::Result<()> {
+    let mut stderr = stderr();
+    let show_progress = stderr.is_terminal();
+
+    let result = copy(&mut |progress| {
+        if show_progress && progress.total > 0 {
+            let _ = write!(
+                stderr,
+                "\rCopying {}%",
+                progress.copied * 100 / progress.total
+            );
+        }
+    });
+
+    if show_progress {
+        let _ = write!(stderr, "\r\x1b[K");
+    }
+
+    result
+}
+
+fn rm(args: &[OsString]) -> ExitCode {
+    if args.is_empty() {
+        return usage_error("Missing name of the item to delete");
+    }
+
+    purge_expired_stages();
+
+    let mut exit_code = ExitCode::SUCCESS;
+    let now = Local::now();
+
+    for query in args {
+        let result = find_entry(query).and_then(|entry| {
+            stage_item(&entry, now).map(|_| ()).map_err(|e| {
+                eprintln!("trash-tui: cannot delete {}: {}", query.display(), e);
+                ExitCode::from(EXIT_FAILURE)
+            })
+        });
+
+        if let Err(code) = result {
+            exit_code = code;
+        }
+    }
+
+    exit_code
+}
+
+fn empty(args: &[OsString]) -> ExitCode {
+    let max_age = match args {
+        [] => None,
+        [flag, age] if flag == "--older-than" => Some(age.to_string_lossy().to_string()),
+        [arg] if arg.to_string_lossy().starts_with("--older-than=") => Some(
+            arg.to_string_lossy()
+                .trim_start_matches("--older-than=")
+                .to_string(),
+        ),
+        _ => return usage_error("empty only takes --older-than <AGE>"),
+    };
+    let max_age = match max_age
+        .map(|age| parse_duration(&age).ok_or(age))
+        .transpose()
+    {
+        Ok(max_age) => max_age,
+        Err(age) => return usage_error(&format!("Invalid age: {}", age)),
+    };
+
+    purge_expired_stages();
+
+    let Some(max_age) = max_age else {
+        return match empty_bin(Local::now()) {
+            (_, None) => ExitCode::SUCCESS,
+            (_, Some(e)) => {
+                eprintln!("trash-tui: cannot empty the trash: {}", e);
+                ExitCode::from(EXIT_FAILURE)
+            }
+        };
+    };
+
+    let now = Local::now();
+    let cutoff = now - max_age;
+    let mut exit_code = ExitCode::SUCCESS;
+
+    for entry in list_trash_entries()
+        .iter()
+        .filter(|entry| entry.date < cutoff)
+    {
+        if let Err(e) = stage_item(entry, now) {
+            eprintln!(
+                "trash-tui: cannot delete {}: {}",
+                entry.content_path.display(),
+                e
+            );
+            exit_code = ExitCode::from(EXIT_FAILURE);
+        }
+    }
+
+    exit_code
+}
+
+// Scripts that delete free the space of earlier deletes, like the UI does
+// when it starts. Not worth failing over, whatever is left is purged next
+// time.
+fn purge_expired_stages() {
+    let _ = purge_expired(Some(grace_period()));
+}
+
+/// Finds the single entry matching a name, a name inside the trash or an
+/// original location. Errors are reported on stderr.
+fn find_entry(query: &OsStr) -> Result<TrashEntry, ExitCode> {
+    find_entry_in(list_trash_entries(), query)
+}
+
+fn find_entry_in(entries: Vec<TrashEntry>, query: &OsStr) -> Result<TrashEntry, ExitCode> {
+    let location = query
+        .as_bytes()
+        .contains(&b'/')
+        .then(|| path::absolute(query).ok())
+        .flatten();
+
+    let mut matches = entries
+        .into_iter()
+        .filter(|entry| match &location {
+            Some(location) => entry.restore_location == *location,
+            None => entry.display_name == query || entry.content_path.file_name() == Some(query),
+        })
+        .collect::<Vec<TrashEntry>>();
+
+    match matches.len() {
+        0 => {
+            eprintln!("trash-tui: no trashed item matches {}", query.display());
+            Err(ExitCode::from(EXIT_NOT_FOUND))
+        }
+        1 => Ok(matches.remove(0)),
+        _ => {
+            eprintln!(
+                "trash-tui: several trashed items match {}, use the name inside the trash:",
+                query.display()
+            );
+            for entry in matches {
+                eprintln!(
+                    "  {}  {}  ({})",
+                    entry.content_path.display(),
+                    entry.date.format("%Y-%m-%d %H:%M:%S"),
+                    entry.restore_location.display()
+                );
+            }
+            Err(ExitCode::from(EXIT_AMBIGUOUS))
+        }
+    }
+}
+
+fn usage_error(error: &str) -> ExitCode {
+    eprintln!("trash-tui: {}\n\n{}", error, USAGE);
+    ExitCode::from(EXIT_USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::ffi::OsStringExt;
+
+    use chrono::TimeZone;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::test_utils::{TempDir, trash_dir, trash_entry};
+
+    #[test]
+    fn can_write_json_list() {
+        let root = TempDir::new("cli-list");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let latin1 = PathBuf::from(OsString::from_vec(b"/home/user/caf\xe9.txt".to_vec()));
+        let entries = [
+            trash_entry(&trash_dir, "notes.txt", Path::new("/home/user/notes.txt")),
+            TrashEntry {
+                date: Local.with_ymd_and_hms(2025, 7, 2, 13, 40, 56).unwrap(),
+                ..trash_entry(&trash_dir, "caf\u{e9}.txt", &latin1)
+            },
+        ];
+        let sizes = HashMap::from([(entries[0].content_path.clone(), 4096)]);
+
+        let mut json = Vec::new();
+        write_list(&mut json, &entries, &sizes, Some("--json")).unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[0]["name"], "notes.txt");
+        assert_eq!(json[0]["size"], 4096);
+        assert_eq!(json[1]["size"], Value::Null);
+        assert_eq!(json[1]["name"], "caf\u{fffd}.txt");
+        assert_eq!(json[1]["name_encoded"], "caf%E9.txt");
+        assert_eq!(json[1]["original_path_encoded"], "/home/user/caf%E9.txt");
+        assert_eq!(
+            json[1]["content_path"],
+            trash_dir
+                .files
+                .join("caf\u{e9}.txt")
+                .to_string_lossy()
+                .as_ref()
+        );
+        assert_eq!(
+            json[1]["deletion_date"],
+            entries[1].date.to_rfc3339().as_str()
+        );
+        assert_eq!(
+            json[1]["trash_root_encoded"],
+            encode_path(&trash_dir.root).as_str()
+        );
+
+        let mut ndjson = Vec::new();
+        write_list(&mut ndjson, &entries, &sizes, Some("--ndjson")).unwrap();
+        let lines = ndjson
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, json.as_array().unwrap().as_slice());
+
+        let mut plain = Vec::new();
+        write_list(&mut plain, &entries[1..], &sizes, None).unwrap();
+        assert_eq!(plain, b"2025-07-02 13:40:56 /home/user/caf\xe9.txt\n");
+    }
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn can_parse_query_and_dir() {
+        assert_eq!(
+            parse_query_and_dir(&args(&["notes.txt"]), "restore"),
+            Ok((OsStr::new("notes.txt"), None))
+        );
+        assert_eq!(
+            parse_query_and_dir(&args(&["--to", "/tmp", "notes.txt"]), "restore"),
+            Ok((OsStr::new("notes.txt"), Some(PathBuf::from("/tmp"))))
+        );
+        assert_eq!(
+            parse_query_and_dir(&args(&["notes.txt", "--to=/tmp"]), "extract"),
+            Ok((OsStr::new("notes.txt"), Some(PathBuf::from("/tmp"))))
+        );
+
+        let usage = Err(ExitCode::from(EXIT_USAGE));
+        assert_eq!(parse_query_and_dir(&args(&[]), "restore"), usage);
+        assert_eq!(parse_query_and_dir(&args(&["a", "b"]), "restore"), usage);
+        assert_eq!(parse_query_and_dir(&args(&["a", "--to"]), "restore"), usage);
+    }
+
+    #[test]
+    fn can_map_usage_errors_to_exit_codes() {
+        let usage = ExitCode::from(EXIT_USAGE);
+        assert_eq!(run(&args(&[])), usage);
+        assert_eq!(run(&args(&["frobnicate"])), usage);
+        assert_eq!(run(&args(&["put"])), usage);
+        assert_eq!(run(&args(&["list", "--xml"])), usage);
+        assert_eq!(run(&args(&["restore"])), usage);
+        assert_eq!(run(&args(&["extract", "a", "b"])), usage);
+        assert_eq!(run(&args(&["rm"])), usage);
+        assert_eq!(run(&args(&["empty", "--older-than", "7y"])), usage);
+        assert_eq!(run(&args(&["empty", "--older-than=soon"])), usage);
+        assert_eq!(run(&args(&["empty", "--now"])), usage);
+        assert_eq!(run(&args(&["purge", "now"])), usage);
+        assert_eq!(run(&args(&["purge", "--expired", "now"])), usage);
+        assert_eq!(run(&args(&["help"])), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn can_find_entry_by_name_or_path() {
+        let root = TempDir::new("cli-find");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let entries = vec![
+            trash_entry(&trash_dir, "notes.txt", Path::new("/home/user/notes.txt")),
+            trash_entry(
+                &trash_dir,
+                "notes_2.txt",
+                Path::new("/home/user/old/notes.txt"),
+            ),
+            trash_entry(&trash_dir, "todo.txt", Path::new("/home/user/todo.txt")),
+        ];
+        let find = |query: &str| {
+            find_entry_in(entries.clone(), OsStr::new(query)).map(|entry| entry.content_path)
+        };
+
+        assert_eq!(find("todo.txt"), Ok(trash_dir.files.join("todo.txt")));
+        assert_eq!(find("notes_2.txt"), Ok(trash_dir.files.join("notes_2.txt")));
+        assert_eq!(
+            find("/home/user/notes.txt"),
+            Ok(trash_dir.files.join("notes.txt"))
+        );
+        assert_eq!(find("notes.txt"), Err(ExitCode::from(EXIT_AMBIGUOUS)));
+        assert_eq!(find("missing.txt"), Err(ExitCode::from(EXIT_NOT_FOUND)));
+        assert_eq!(
+            find("/home/user/missing.txt"),
+            Err(ExitCode::from(EXIT_NOT_FOUND))
+        );
+    }
+}
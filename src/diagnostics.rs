@@ -0,0 +1,224 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ratatui::widgets::TableState;
+
+use crate::{
+    io::{get_trash_dirs, list_files_from_dir},
+    trash_dir::TrashDir,
+    trash_entry::TrashEntry,
+    trash_info::TrashInfoError,
+};
+
+pub enum Problem {
+    /// The info file exists but can't be parsed
+    InvalidInfo(TrashInfoError),
+    /// A file in `files/` without a matching `info/*.trashinfo`
+    OrphanedContent,
+    /// An info file whose content is missing from `files/`
+    DanglingInfo,
+}
+
+pub struct Diagnostic {
+    pub problem: Problem,
+    /// The info file, or the content for orphaned files
+    pub path: PathBuf,
+    pub trash_dir: TrashDir,
+}
+
+impl Diagnostic {
+    pub fn label(&self) -> &'static str {
+        match self.problem {
+            Problem::InvalidInfo(_) => "broken",
+            Problem::OrphanedContent => "orphan",
+            Problem::DanglingInfo => "dangling",
+        }
+    }
+
+    pub fn reason(&self) -> String {
+        match &self.problem {
+            Problem::InvalidInfo(e) => e.to_string(),
+            Problem::OrphanedContent => "No trash info file for this item".to_string(),
+            Problem::DanglingInfo => "The trashed content is missing".to_string(),
+        }
+    }
+
+    /// The content in `files/` that belongs to the diagnostic, it may not
+    /// exist. Stray files in `info/` don't belong to any content.
+    pub fn content_path(&self) -> Option<PathBuf> {
+        match self.problem {
+            Problem::OrphanedContent => Some(self.path.clone()),
+            Problem::InvalidInfo(TrashInfoError::InvalidInfoFileName) => None,
+            _ => Some(
+                self.trash_dir
+                    .files
+                    .join(self.path.file_stem().unwrap_or_default()),
+            ),
+        }
+    }
+}
+
+pub struct DiagnosticsContainer {
+    pub items: Vec<Diagnostic>,
+    pub state: TableState,
+}
+
+impl DiagnosticsContainer {
+    pub fn new() -> Self {
+        let mut diagnostics = DiagnosticsContainer {
+            items: Vec::new(),
+            state: TableState::default(),
+        };
+
+        diagnostics.refresh();
+
+        diagnostics
+    }
+
+    pub fn refresh(&mut self) {
+        self.items = get_trash_dirs().iter().flat_map(scan_trash_dir).collect();
+
+        match self.state.selected() {
+            _ if self.items.is_empty() => self.state.select(None),
+            Some(index) if index >= self.items.len() => self.state.select_last(),
+            None => self.state.select_first(),
+            _ => {}
+        }
+    }
+
+    pub fn next(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(
+                self.state
+                    .selected()
+                    .map_or(0, |i| (i + 1) % self.items.len()),
+            ));
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(match self.state.selected() {
+                Some(0) | None => self.items.len() - 1,
+                Some(i) => i - 1,
+            }));
+        }
+    }
+
+    pub fn get_selected_item(&self) -> Option<&Diagnostic> {
+        self.state
+            .selected()
+            .and_then(|index| self.items.get(index))
+    }
+}
+
+pub fn scan_trash_dir(trash_dir: &TrashDir) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let diagnostic = |problem, path: &Path| Diagnostic {
+        problem,
+        path: path.to_path_buf(),
+        trash_dir: trash_dir.clone(),
+    };
+
+    for info_path in list_files_from_dir(&trash_dir.info).unwrap_or_default() {
+        if info_path.extension().is_none_or(|ext| ext != "trashinfo") {
+            diagnostics.push(diagnostic(
+                Problem::InvalidInfo(TrashInfoError::InvalidInfoFileName),
+                &info_path,
+            ));
+            continue;
+        }
+
+        match TrashEntry::from_trash_info(&info_path, trash_dir) {
+            Err(e) => diagnostics.push(diagnostic(Problem::InvalidInfo(e), &info_path)),
+            // symlink_metadata so that a trashed dangling symlink still counts
+            Ok(entry) if fs::symlink_metadata(&entry.content_path).is_err() => {
+                diagnostics.push(diagnostic(Problem::DanglingInfo, &info_path))
+            }
+            Ok(_) => {}
+        }
+    }
+
+    for content_path in list_files_from_dir(&trash_dir.files).unwrap_or_default() {
+        let info_path = trash_dir.info_path_for(content_path.file_name().unwrap_or_default());
+
+        if fs::symlink_metadata(info_path).is_err() {
+            diagnostics.push(diagnostic(Problem::OrphanedContent, &content_path));
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+    use crate::{
+        io::{delete_diagnostic, unstage_item},
+        test_utils::{TempDir, trash_dir},
+    };
+
+    #[test]
+    fn can_find_broken_entries() {
+        let root = TempDir::new("diagnostics");
+        let trash_dir = trash_dir(&root);
+
+        fs::write(trash_dir.files.join("orphan.txt"), "").unwrap();
+        fs::write(
+            trash_dir.info.join("dangling.txt.trashinfo"),
+            "[Trash Info]\nPath=/tmp/dangling.txt\nDeletionDate=2025-07-02T13:40:56",
+        )
+        .unwrap();
+        fs::write(trash_dir.files.join("broken.txt"), "").unwrap();
+        fs::write(trash_dir.info.join("broken.txt.trashinfo"), "garbage").unwrap();
+
+        let mut labels = scan_trash_dir(&trash_dir)
+            .iter()
+            .map(|diagnostic| diagnostic.label())
+            .collect::<Vec<&str>>();
+        labels.sort();
+
+        assert_eq!(labels, vec!["broken", "dangling", "orphan"]);
+    }
+
+    #[test]
+    fn can_undo_deleting_broken_entries() {
+        let root = TempDir::new("diagnostics-delete");
+        let trash_dir = trash_dir(&root);
+
+        fs::write(trash_dir.files.join("orphan.txt"), "orphan").unwrap();
+        fs::write(trash_dir.files.join("broken.txt"), "broken").unwrap();
+        fs::write(trash_dir.info.join("broken.txt.trashinfo"), "garbage").unwrap();
+        fs::write(trash_dir.info.join("notes.bak"), "stray").unwrap();
+        let files = |trash_dir: &TrashDir| {
+            let mut files = [&trash_dir.files, &trash_dir.info]
+                .into_iter()
+                .flat_map(|dir| list_files_from_dir(dir).unwrap())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+        let before = files(&trash_dir);
+
+        let deleted = scan_trash_dir(&trash_dir)
+            .iter()
+            .map(|diagnostic| delete_diagnostic(diagnostic, Local::now()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(deleted.len(), 3);
+        assert!(files(&trash_dir).is_empty());
+
+        for (entry, stage) in &deleted {
+            unstage_item(stage, entry).unwrap();
+        }
+        assert_eq!(files(&trash_dir), before);
+        assert_eq!(
+            fs::read_to_string(trash_dir.info.join("notes.bak")).unwrap(),
+            "stray"
+        );
+        assert!(list_files_from_dir(&trash_dir.staging).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Error, Read},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use similar::{ChangeTag, TextDiff};
+
+/// Bigger files are only compared, not diffed line by line
+const MAX_DIFF_BYTES: u64 = 1024 * 1024;
+const CONTEXT_LINES: usize = 3;
+
+pub enum DiffLine {
+    Hunk(String),
+    Added(String),
+    Removed(String),
+    Unchanged(String),
+}
+
+/// What restoring the trashed item over the existing one would change
+pub enum Diff {
+    /// Unified diff from the existing file to the trashed one
+    Text(Vec<DiffLine>),
+    /// Paths relative to both directories
+    Directory {
+        added: Vec<PathBuf>,
+        removed: Vec<PathBuf>,
+        changed: Vec<PathBuf>,
+    },
+    /// Anything that can't be shown line by line, e.g. binary files
+    Summary(String),
+    /// Still being compared on another thread
+    Pending(Receiver<Diff>),
+}
+
+impl Diff {
+    /// Compares the trashed item at `trashed` with what's at `existing` now.
+    pub fn new(trashed: &Path, existing: &Path) -> Self {
+        diff_paths(trashed, existing)
+            .unwrap_or_else(|e| Diff::Summary(format!("Cannot compare: {}", e)))
+    }
+
+    /// Like [`Diff::new`], but the comparison runs on another thread, big
+    /// directories take a while. See [`Diff::receive`].
+    pub fn spawn(trashed: PathBuf, existing: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        // Nobody waits for the result once the diff is closed
+        thread::spawn(move || sender.send(Diff::new(&trashed, &existing)));
+
+        Diff::Pending(receiver)
+    }
+
+    /// Takes the result of a pending diff when it's ready
+    pub fn receive(&mut self) {
+        let Diff::Pending(receiver) = self else {
+            return;
+        };
+
+        match receiver.try_recv() {
+            Ok(diff) => *self = diff,
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                *self = Diff::Summary("Cannot compare the items".to_string())
+            }
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Diff::Pending(_))
+    }
+}
+
+fn diff_paths(trashed: &Path, existing: &Path) -> Result<Diff, Error> {
+    let (a, b) = (
+        fs::symlink_metadata(trashed)?,
+        fs::symlink_metadata(existing)?,
+    );
+
+    if a.is_dir() && b.is_dir() {
+        return diff_dirs(trashed, existing);
+    }
+
+    let is_same = if a.is_file() && b.is_file() && a.len().max(b.len()) <= MAX_DIFF_BYTES {
+        // Each file is read once, whether it's diffed or only compared
+        let (new, old) = (fs::read(trashed)?, fs::read(existing)?);
+        if let (Some(new), Some(old)) = (as_text(&new), as_text(&old)) {
+            return Ok(diff_text(old, new));
+        }
+        new == old
+    } else {
+        same_item(trashed, existing)?
+    };
+
+    Ok(Diff::Summary(
+        match (a.file_type() == b.file_type(), is_same) {
+            (false, _) => "The trashed item and the existing one are of different types",
+            (true, true) => "The items are identical",
+            (true, false) => "The items differ",
+        }
+        .to_string(),
+    ))
+}
+
+fn diff_text(old: &str, new: &str) -> Diff {
+    let diff = TextDiff::from_lines(old, new);
+
+    let mut lines = Vec::new();
+    for hunk in diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .iter_hunks()
+    {
+        lines.push(DiffLine::Hunk(hunk.header().to_string()));
+        for change in hunk.iter_changes() {
+            let line = change.value().trim_end_matches('\n').replace('\t', "    ");
+            lines.push(match change.tag() {
+                ChangeTag::Insert => DiffLine::Added(line),
+                ChangeTag::Delete => DiffLine::Removed(line),
+                ChangeTag::Equal => DiffLine::Unchanged(line),
+            });
+        }
+    }
+
+    match lines.is_empty() {
+        true => Diff::Summary("The files are identical".to_string()),
+        false => Diff::Text(lines),
+    }
+}
+
+// Valid UTF-8 and without NUL bytes
+fn as_text(content: &[u8]) -> Option<&str> {
+    match content.contains(&0) {
+        true => None,
+        false => std::str::from_utf8(content).ok(),
+    }
+}
+
+// Files by content, symlinks by target. Directories aren't looked into.
+fn same_item(a: &Path, b: &Path) -> Result<bool, Error> {
+    let (meta_a, meta_b) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+
+    if meta_a.file_type() != meta_b.file_type() {
+        return Ok(false);
+    }
+    if meta_a.is_symlink() {
+        return Ok(fs::read_link(a)? == fs::read_link(b)?);
+    }
+    if !meta_a.is_file() || meta_a.len() != meta_b.len() {
+        return Ok(meta_a.is_dir());
+    }
+
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut chunk_a, mut chunk_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    let mut remaining = meta_a.len();
+    while remaining > 0 {
+        let size = remaining.min(chunk_a.len() as u64) as usize;
+        a.read_exact(&mut chunk_a[..size])?;
+        b.read_exact(&mut chunk_b[..size])?;
+        if chunk_a[..size] != chunk_b[..size] {
+            return Ok(false);
+        }
+        remaining -= size as u64;
+    }
+
+    Ok(true)
+}
+
+fn diff_dirs(trashed: &Path, existing: &Path) -> Result<Diff, Error> {
+    let new = list_tree(trashed)?;
+    let old = list_tree(existing)?;
+
+    let added = new
+        .keys()
+        .filter(|path| !old.contains_key(*path))
+        .cloned()
+        .collect();
+    let removed = old
+        .keys()
+        .filter(|path| !new.contains_key(*path))
+        .cloned()
+        .collect();
+
+    let mut changed = Vec::new();
+    for path in new.keys().filter(|path| old.contains_key(*path)) {
+        if !same_item(&trashed.join(path), &existing.join(path))? {
+            changed.push(path.clone());
+        }
+    }
+
+    Ok(Diff::Directory {
+        added,
+        removed,
+        changed,
+    })
+}
+
+// Every path below `root`, relative to it, and whether it's a directory
+fn list_tree(root: &Path) -> Result<BTreeMap<PathBuf, bool>, Error> {
+    let mut paths = BTreeMap::new();
+    let mut dirs = vec![PathBuf::new()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            let is_dir = entry.file_type()?.is_dir();
+
+            if is_dir {
+                dirs.push(path.clone());
+            }
+            paths.insert(path, is_dir);
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn can_diff_files_and_dirs() {
+        let root = TempDir::new("diff");
+        for dir in ["trashed/sub", "existing/sub"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("trashed/notes.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(root.join("existing/notes.txt"), "one\n2\nthree\n").unwrap();
+        fs::write(root.join("trashed/sub/same.txt"), "same").unwrap();
+        fs::write(root.join("existing/sub/same.txt"), "same").unwrap();
+        fs::write(root.join("trashed/new.txt"), "").unwrap();
+        fs::write(root.join("existing/old.txt"), "").unwrap();
+
+        let Diff::Text(lines) = Diff::new(
+            &root.join("trashed/notes.txt"),
+            &root.join("existing/notes.txt"),
+        ) else {
+            panic!("expected a text diff");
+        };
+        assert!(matches!(&lines[0], DiffLine::Hunk(header) if header == "@@ -1,3 +1,3 @@"));
+        assert!(matches!(&lines[2], DiffLine::Removed(line) if line == "2"));
+        assert!(matches!(&lines[3], DiffLine::Added(line) if line == "two"));
+
+        let Diff::Directory {
+            added,
+            removed,
+            changed,
+        } = Diff::new(&root.join("trashed"), &root.join("existing"))
+        else {
+            panic!("expected a directory diff");
+        };
+        assert_eq!(added, [PathBuf::from("new.txt")]);
+        assert_eq!(removed, [PathBuf::from("old.txt")]);
+        assert_eq!(changed, [PathBuf::from("notes.txt")]);
+
+        fs::write(root.join("trashed/data.bin"), b"\0\x01").unwrap();
+        fs::write(root.join("existing/data.bin"), b"\0\x02").unwrap();
+        assert!(matches!(
+            Diff::new(&root.join("trashed/data.bin"), &root.join("existing/data.bin")),
+            Diff::Summary(summary) if summary == "The items differ"
+        ));
+
+        let mut diff = Diff::spawn(root.join("trashed"), root.join("existing"));
+        while diff.is_pending() {
+            diff.receive();
+        }
+        assert!(matches!(diff, Diff::Directory { .. }));
+    }
+}
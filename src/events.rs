@@ -1,15 +1,29 @@
-use std::{fs, io};
+use std::{
+    env, fs, io,
+    path::{self, Path},
+};
 
+use chrono::Local;
 use crossterm::event::{Event, KeyCode, KeyEvent};
-use tui_input::backend::crossterm::EventHandler;
+use tui_input::{Input, backend::crossterm::EventHandler};
 
 use crate::{
     app::App,
-    io::{delete_item, empty_bin, restore_item},
+    browser::Browser,
+    diagnostics::Problem,
+    diff::Diff,
+    io::{
+        adopt_orphan, delete_diagnostic, empty_bin, fix_diagnostic, guess_original_path, stage_item,
+    },
+    journal::{Operation, Record},
+    restore::{Conflict, Resolution, RestoreQueue},
+    trash_entry::TrashEntry,
     ui::Message,
-    utils::{Choice, Mode, SortMode},
+    utils::{Choice, Mode, PathAction, SortMode, complete_dir, expand_home},
 };
 
+const DIFF_PAGE: u16 = 20;
+
 impl App {
     pub fn handle_key_press(&mut self, key: KeyEvent, event: &Event) -> Result<bool, io::Error> {
         // Clear message on any key press
@@ -21,6 +35,30 @@ impl App {
             return self.handle_choice_action(key);
         }
 
+        if self.diff.is_some() {
+            self.handle_diff_action(key);
+            return Ok(false);
+        }
+
+        if self.restore_queue.is_some()
+            && !matches!(self.mode, Mode::PathInput(PathAction::RenameRestore))
+        {
+            self.handle_conflict_action(key);
+            return Ok(false);
+        }
+
+        if matches!(self.mode, Mode::PathInput(_)) && key.code == KeyCode::Tab {
+            if let Some(completed) = complete_dir(self.path_input.value()) {
+                self.path_input = Input::new(completed);
+            }
+            return Ok(false);
+        }
+
+        if matches!(self.mode, Mode::ListView) && self.browser.is_some() {
+            self.handle_browser_action(key);
+            return Ok(false);
+        }
+
         match self.mode {
             Mode::ListView => match key.code {
                 KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
@@ -34,36 +72,188 @@ impl App {
                 KeyCode::PageUp | KeyCode::Left | KeyCode::Char('h') => {
                     self.list_container.scroll_prev()
                 }
-                KeyCode::Enter => {
-                    if self.list_container.get_slected_item().is_some() {
-                        self.choice_popup = Some(Choice::Restore);
-                    }
+                KeyCode::Enter if !self.list_container.get_target_items().is_empty() => {
+                    self.choice_popup = Some(Choice::Restore);
+                }
+                KeyCode::Char('t') if !self.list_container.get_target_items().is_empty() => {
+                    self.ask_for_dir(PathAction::RestoreTo)
                 }
-                KeyCode::Char('d') => {
-                    if self.list_container.get_slected_item().is_some() {
-                        self.choice_popup = Some(Choice::Delete);
+                KeyCode::Char('x') if !self.list_container.get_target_items().is_empty() => {
+                    self.ask_for_dir(PathAction::Extract)
+                }
+                KeyCode::Char('b') => match self.list_container.get_slected_item() {
+                    Some(item) if Browser::can_browse(item) => {
+                        self.browser = Some(Browser::new(item.clone()))
+                    }
+                    Some(_) => {
+                        self.message = Some(Message::error(
+                            "Only directories can be browsed".to_string(),
+                        ))
                     }
+                    None => {}
+                },
+                KeyCode::Char('d') if !self.list_container.get_target_items().is_empty() => {
+                    self.choice_popup = Some(Choice::Delete);
                 }
+                KeyCode::Char(' ') => {
+                    self.list_container.toggle_mark();
+                    self.list_container.next();
+                }
+                KeyCode::Char('a') => self.list_container.toggle_mark_all(),
+                KeyCode::Char('i') => self.list_container.invert_marks(),
+                KeyCode::Char('m') => self.list_container.mark_visible(),
                 KeyCode::Char('e') => {
                     self.choice_popup = Some(Choice::Empty);
                 }
+                KeyCode::Char('u') => self.undo(0),
+                KeyCode::Char('H') => self.mode = Mode::History,
+                KeyCode::Char('D') => self.show_diff(),
+                KeyCode::Char('o') => self.show_location = !self.show_location,
+                KeyCode::Char('g') => self.list_container.toggle_grouping(),
+                KeyCode::Tab => self.list_container.toggle_fold(),
+                KeyCode::Char('p') => self.show_preview = !self.show_preview,
+                KeyCode::Char('!') => {
+                    self.diagnostics.refresh();
+                    self.mode = Mode::Diagnostics;
+                }
+                _ => {}
+            },
+            Mode::Diagnostics => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    self.list_container.refresh(&self.sort_mode);
+                    self.mode = Mode::ListView;
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.diagnostics.next(),
+                KeyCode::Up | KeyCode::Char('k') => self.diagnostics.prev(),
+                KeyCode::Char('r') => self.diagnostics.refresh(),
+                KeyCode::Char('d') if self.diagnostics.get_selected_item().is_some() => {
+                    self.choice_popup = Some(Choice::DeleteBroken);
+                }
+                KeyCode::Char('f') => {
+                    if let Some(diagnostic) = self.diagnostics.get_selected_item() {
+                        self.message = Some(match fix_diagnostic(diagnostic) {
+                            Ok(()) => Message::info("Item fixed successfully".to_string()),
+                            Err(e) => Message::error(format!("Error fixing item: {}", e)),
+                        });
+                        self.diagnostics.refresh();
+                    }
+                }
+                KeyCode::Char('a') => match self.diagnostics.get_selected_item() {
+                    Some(diagnostic) if matches!(diagnostic.problem, Problem::OrphanedContent) => {
+                        self.path_input = Input::new(
+                            guess_original_path(&diagnostic.path, &diagnostic.trash_dir)
+                                .to_string_lossy()
+                                .to_string(),
+                        );
+                        self.mode = Mode::PathInput(PathAction::Adopt);
+                    }
+                    Some(_) => {
+                        self.message = Some(Message::error(
+                            "Only orphaned files can be adopted".to_string(),
+                        ));
+                    }
+                    None => {}
+                },
+                _ => {}
+            },
+            Mode::PathInput(PathAction::Adopt) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Diagnostics,
+                KeyCode::Enter => {
+                    if let Some(diagnostic) = self.diagnostics.get_selected_item() {
+                        self.message = Some(
+                            match adopt_orphan(
+                                &diagnostic.path,
+                                &diagnostic.trash_dir,
+                                &expand_home(self.path_input.value()),
+                            ) {
+                                Ok(()) => Message::info("Item adopted successfully".to_string()),
+                                Err(e) => Message::error(format!("Error adopting item: {}", e)),
+                            },
+                        );
+                        self.diagnostics.refresh();
+                    }
+                    self.mode = Mode::Diagnostics;
+                }
+                _ => {
+                    self.path_input.handle_event(event);
+                }
+            },
+            Mode::PathInput(PathAction::RestoreTo | PathAction::Extract) => match key.code {
+                KeyCode::Esc => self.mode = Mode::ListView,
+                KeyCode::Enter => {
+                    match path::absolute(expand_home(self.path_input.value())) {
+                        // Run by the main loop, like restores
+                        Ok(dir) if matches!(self.mode, Mode::PathInput(PathAction::Extract)) => {
+                            self.pending_extract = Some((self.get_target_items(), dir));
+                        }
+                        Ok(dir) => {
+                            self.restore_queue = Some(
+                                RestoreQueue::new(
+                                    self.get_target_items(),
+                                    self.get_trashed_items(),
+                                )
+                                .with_target_dir(&dir),
+                            );
+                        }
+                        Err(e) => {
+                            self.message = Some(Message::error(format!("Invalid directory: {}", e)))
+                        }
+                    }
+                    self.mode = Mode::ListView;
+                }
+                _ => {
+                    self.path_input.handle_event(event);
+                }
+            },
+            Mode::PathInput(PathAction::RenameRestore) => match key.code {
+                KeyCode::Esc => self.mode = Mode::ListView,
+                KeyCode::Enter => {
+                    if let Some(queue) = &mut self.restore_queue
+                        && let Some(job) = queue.conflict()
+                    {
+                        // Relative names stay in the original directory
+                        let target = job
+                            .target
+                            .parent()
+                            .unwrap_or(Path::new("/"))
+                            .join(expand_home(self.path_input.value()));
+
+                        queue.resolve(Resolution::Rename(target));
+                    }
+                    self.mode = Mode::ListView;
+                }
+                _ => {
+                    self.path_input.handle_event(event);
+                }
+            },
+            Mode::History => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('H') => {
+                    self.mode = Mode::ListView
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.journal.next(),
+                KeyCode::Up | KeyCode::Char('k') => self.journal.prev(),
+                KeyCode::Char('u') | KeyCode::Enter => {
+                    if let Some(index) = self.journal.state.selected() {
+                        self.undo(index);
+                    }
+                }
+                KeyCode::Char('P') => self.choice_popup = Some(Choice::Purge),
                 _ => {}
             },
             Mode::Filtering => match key.code {
                 KeyCode::Enter => self.mode = Mode::ListView,
                 KeyCode::Esc => {
                     self.input.reset();
+                    self.list_container.set_filter("");
                     self.mode = Mode::ListView;
-                    if self.list_container.get_slected_item().is_none() {
-                        self.list_container.next();
-                    }
                 }
                 KeyCode::Up => self.list_container.prev(),
                 KeyCode::Down => self.list_container.next(),
                 KeyCode::PageDown => self.list_container.scroll_next(),
                 KeyCode::PageUp => self.list_container.scroll_prev(),
                 _ => {
-                    self.input.handle_event(&event);
+                    self.input.handle_event(event);
+                    self.list_container.set_filter(self.input.value());
                 }
             },
             Mode::Sorting => {
@@ -73,6 +263,8 @@ impl App {
                     KeyCode::Char('n') => SortMode::NameDesc,
                     KeyCode::Char('d') => SortMode::DateAsc,
                     KeyCode::Char('D') => SortMode::DateDesc,
+                    KeyCode::Char('z') => SortMode::SizeDesc,
+                    KeyCode::Char('Z') => SortMode::SizeAsc,
                     _ => SortMode::DateAsc,
                 };
                 self.list_container.sort(&self.sort_mode);
@@ -89,57 +281,237 @@ impl App {
             KeyCode::Char('q') => return Ok(true),
             KeyCode::Char('y') | KeyCode::Enter => {
                 match choice {
-                    Choice::Restore | Choice::Override => {
-                        let item = self.list_container.get_slected_item().unwrap();
-
-                        if matches!(choice, Choice::Override) {
-                            match fs::exists(&item.restore_location) {
-                                Ok(false) => {}
-                                Ok(true) => {
-                                    self.choice_popup = Some(Choice::Override);
-                                    return Ok(false);
-                                }
-                                Err(e) => {
-                                    self.message = Some(Message::error(format!(
-                                        "Error checking file existence: {}",
-                                        e
-                                    )));
-                                    return Ok(false);
-                                }
-                            }
-                        }
-
-                        self.message = Some(match restore_item(item) {
-                            Ok(()) => Message::info("Item restored successfully".to_string()),
-                            Err(e) => Message::error(format!("Error restoring item: {}", e)),
-                        });
-                        self.choice_popup = None;
+                    // Run by the main loop, which can draw the progress
+                    Choice::Restore => {
+                        self.restore_queue = Some(RestoreQueue::new(
+                            self.get_target_items(),
+                            self.get_trashed_items(),
+                        ));
+                        return Ok(false);
                     }
+                    // Staged rather than deleted, so it can be undone
                     Choice::Delete => {
-                        let item = self.list_container.get_slected_item().unwrap();
+                        let now = Local::now();
+                        let mut operations = Vec::new();
+                        let results = self
+                            .list_container
+                            .get_target_items()
+                            .into_iter()
+                            .map(|item| {
+                                let result = stage_item(&item, now).map(|stage| {
+                                    operations.push(Operation::Deleted {
+                                        entry: item.clone(),
+                                        stage,
+                                    })
+                                });
+                                (item, result)
+                            })
+                            .collect::<Vec<_>>();
 
-                        self.message = Some(match delete_item(item) {
-                            Ok(()) => Message::info("Item deleted successfully".to_string()),
-                            Err(e) => Message::error(format!("Error deleting item: {}", e)),
-                        });
-                        self.choice_popup = None;
+                        self.journal.push(operations);
+                        self.message = Some(summarize(&results, "deleted"));
+                    }
+                    Choice::DeleteBroken => {
+                        if let Some(diagnostic) = self.diagnostics.get_selected_item() {
+                            self.message =
+                                Some(match delete_diagnostic(diagnostic, Local::now()) {
+                                    Ok((entry, stage)) => {
+                                        self.journal
+                                            .push(vec![Operation::Deleted { entry, stage }]);
+                                        Message::info("Item deleted successfully".to_string())
+                                    }
+                                    Err(e) => Message::error(format!("Error deleting item: {}", e)),
+                                });
+                        }
+                        self.diagnostics.refresh();
                     }
                     Choice::Empty => {
-                        self.message = Some(match empty_bin() {
-                            Ok(()) => Message::info("Trash emptied successfully".to_string()),
-                            Err(e) => Message::error(format!("Error emptying trash: {}", e)),
+                        let (staged, error) = empty_bin(Local::now());
+                        // Whatever was emptied before an error can be undone
+                        self.journal.push(
+                            staged
+                                .into_iter()
+                                .map(|(entry, stage)| Operation::Deleted { entry, stage })
+                                .collect(),
+                        );
+                        self.message = Some(match error {
+                            None => Message::info("Trash emptied successfully".to_string()),
+                            Some(e) => Message::error(format!("Error emptying trash: {}", e)),
                         });
                         self.choice_popup = None;
                     }
+                    Choice::Purge => {
+                        self.message = Some(match self.journal.purge() {
+                            Ok(()) => Message::info("Deleted items purged".to_string()),
+                            Err(e) => Message::error(format!("Error purging items: {}", e)),
+                        });
+                    }
                 };
 
                 self.list_container.refresh(&self.sort_mode);
             }
-            KeyCode::Char('n') | KeyCode::Esc | _ => {
+            _ => {
                 self.choice_popup = None;
             }
         }
 
-        return Ok(false);
+        Ok(false)
+    }
+
+    fn handle_browser_action(&mut self, key: KeyEvent) {
+        let Some(browser) = &mut self.browser else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.browser = None,
+            KeyCode::Down | KeyCode::Char('j') => browser.next(),
+            KeyCode::Up | KeyCode::Char('k') => browser.prev(),
+            KeyCode::Right | KeyCode::Char('l') => browser.enter(),
+            // Leaving the trashed directory itself closes the browser
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace if browser.is_at_top() => {
+                self.browser = None
+            }
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => browser.leave(),
+            KeyCode::Enter if browser.get_selected_item().is_some() => {
+                self.choice_popup = Some(Choice::Restore);
+            }
+            KeyCode::Char('t') if browser.get_selected_item().is_some() => {
+                self.ask_for_dir(PathAction::RestoreTo)
+            }
+            KeyCode::Char('x') if browser.get_selected_item().is_some() => {
+                self.ask_for_dir(PathAction::Extract)
+            }
+            KeyCode::Char('p') => self.show_preview = !self.show_preview,
+            KeyCode::Char('D') => self.show_diff(),
+            _ => {}
+        }
+    }
+
+    // Compares the selected item with what's at its original location now
+    fn show_diff(&mut self) {
+        let selected = match &self.browser {
+            Some(browser) => browser.get_selected_item(),
+            None => self.list_container.get_slected_item().cloned(),
+        };
+
+        match selected {
+            Some(item) if fs::symlink_metadata(&item.restore_location).is_ok() => {
+                self.diff = Some((Diff::spawn(item.content_path, item.restore_location), 0));
+            }
+            Some(_) => {
+                self.message = Some(Message::error(
+                    "Nothing at the original location to compare with".to_string(),
+                ));
+            }
+            None => {}
+        }
+    }
+
+    fn handle_diff_action(&mut self, key: KeyEvent) {
+        let Some((_, scroll)) = &mut self.diff else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('d') | KeyCode::Char('D') => {
+                self.diff = None
+            }
+            KeyCode::Down | KeyCode::Char('j') => *scroll = scroll.saturating_add(1),
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::PageDown => *scroll = scroll.saturating_add(DIFF_PAGE),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(DIFF_PAGE),
+            _ => {}
+        }
+    }
+
+    // The path input starts in the current directory
+    fn ask_for_dir(&mut self, action: PathAction) {
+        let mut dir = env::current_dir()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !dir.ends_with('/') {
+            dir.push('/');
+        }
+
+        self.path_input = Input::new(dir);
+        self.mode = Mode::PathInput(action);
+    }
+
+    fn undo(&mut self, index: usize) {
+        let action = self.journal.records.get(index).map(Record::undo_action);
+
+        self.message = Some(match (action, self.journal.undo(index)) {
+            (Some(action), Some(results)) => summarize(&results, action),
+            _ => Message::error("Nothing to undo".to_string()),
+        });
+        self.list_container.refresh(&self.sort_mode);
+    }
+
+    fn handle_conflict_action(&mut self, key: KeyEvent) {
+        let Some(queue) = &mut self.restore_queue else {
+            return;
+        };
+        let Some(job) = queue.conflict() else {
+            return;
+        };
+
+        let is_missing_parent = matches!(job.conflict, Some(Conflict::MissingParent(_)));
+        let has_trashed_parent = matches!(job.conflict, Some(Conflict::MissingParent(Some(_))));
+
+        match key.code {
+            KeyCode::Char('o') if !is_missing_parent => queue.resolve(Resolution::Overwrite),
+            KeyCode::Char('k') if !is_missing_parent => queue.resolve(Resolution::KeepBoth),
+            KeyCode::Char('m') if !is_missing_parent && job.can_merge() => {
+                queue.resolve(Resolution::Merge)
+            }
+            KeyCode::Char('c') if is_missing_parent => queue.resolve(Resolution::CreateParents),
+            KeyCode::Char('p') if has_trashed_parent => queue.resolve(Resolution::RestoreParent),
+            KeyCode::Char('s') => queue.resolve(Resolution::Skip),
+            KeyCode::Char('d') if !is_missing_parent => {
+                self.diff = Some((
+                    Diff::spawn(job.entry.content_path.clone(), job.target.clone()),
+                    0,
+                ))
+            }
+            KeyCode::Char('r') => {
+                self.path_input = Input::new(
+                    job.target
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                );
+                self.mode = Mode::PathInput(PathAction::RenameRestore);
+            }
+            KeyCode::Char('a') => queue.apply_to_all = !queue.apply_to_all,
+            KeyCode::Char('q') | KeyCode::Esc => queue.cancel(),
+            _ => {}
+        }
+    }
+}
+
+/// One line per failed item, so a bulk action never fails silently.
+pub fn summarize(results: &[(TrashEntry, Result<(), io::Error>)], action: &str) -> Message {
+    let failures = results
+        .iter()
+        .filter_map(|(item, result)| {
+            result
+                .as_ref()
+                .err()
+                .map(|e| format!("{}: {}", item.display_name.to_string_lossy(), e))
+        })
+        .collect::<Vec<String>>();
+
+    match (results.len(), failures.len()) {
+        (1, 0) => Message::info(format!("Item {} successfully", action)),
+        (total, 0) => Message::info(format!("{} items {} successfully", total, action)),
+        (total, failed) => Message::error(format!(
+            "{} of {} items {}, failed: {}",
+            total - failed,
+            total,
+            action,
+            failures.join("; ")
+        )),
     }
 }
@@ -1,32 +1,74 @@
 use std::{
+    collections::HashSet,
     env,
-    fs::{self, File},
-    io::{BufReader, Error, Lines},
+    ffi::{CString, OsStr, OsString},
+    fs::{self, DirBuilder, File, FileTimes, Metadata, OpenOptions},
+    io::{Error, ErrorKind, Read, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt, lchown, symlink},
+    },
     path::{Path, PathBuf},
 };
 
-use crate::{trash_entry::TrashEntry, ui::Message};
+use chrono::{DateTime, Local, TimeDelta, TimeZone};
 
-pub fn get_trash_dirs() -> (PathBuf, PathBuf, PathBuf) {
-    let mut current_path = Path::read_dir(Path::new(".")).unwrap_or_else(|e| panic!("{}", e));
+use crate::{
+    diagnostics::{Diagnostic, Problem},
+    sizes::{forget_size, item_size, remember_size},
+    trash_dir::TrashDir,
+    trash_entry::TrashEntry,
+    trash_info::TrashInfo,
+};
+
+// Pseudo filesystems that can never hold a trash directory. Some of them
+// (autofs in particular) may block or trigger a mount when probed.
+const IGNORED_FS_TYPES: [&str; 17] = [
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "securityfs",
+    "sysfs",
+];
 
-    let local_trash_dirs = current_path.find(|dir| match dir {
-        Ok(dir) => {
-            let p = dir.path();
+/// Returns every trash directory available to the current user: the home
+/// trash first, followed by the top directory trashes of all mounted
+/// filesystems (`$topdir/.Trash/$uid` and `$topdir/.Trash-$uid`).
+pub fn get_trash_dirs() -> Vec<TrashDir> {
+    let mut trash_dirs = vec![home_trash_dir()];
 
-            p.is_dir()
-                && p.file_name()
-                    .and_then(|name| name.to_str())
-                    .map_or(false, |val| val.starts_with(".Trash-"))
-                && p.join("files").exists()
-                && p.join("info").exists()
+    if let Some(uid) = current_uid() {
+        for mount_point in get_mount_points() {
+            trash_dirs.extend(find_topdir_trashes(&mount_point, uid));
         }
-        _ => false,
+    }
+
+    // The same trash can be reachable through several mount points (bind
+    // mounts), only keep the first occurrence.
+    let mut seen = HashSet::new();
+    trash_dirs.retain(|trash_dir| match fs::metadata(&trash_dir.root) {
+        Ok(meta) => seen.insert((meta.dev(), meta.ino())),
+        Err(_) => false,
     });
 
-    let trash_dir = match local_trash_dirs {
-        Some(dir) if dir.is_ok() => dir.unwrap().path(),
-        _ => PathBuf::from_iter([
+    trash_dirs
+}
+
+pub fn home_trash_dir() -> TrashDir {
+    let trash_dir = TrashDir::new(
+        &PathBuf::from_iter([
             env::var("HOME")
                 .unwrap_or_else(|_| panic!("Error getting home directory"))
                 .as_str(),
@@ -34,32 +76,114 @@ pub fn get_trash_dirs() -> (PathBuf, PathBuf, PathBuf) {
             "share",
             "Trash",
         ]),
-    };
+        None,
+    );
 
-    validate_dir(&trash_dir);
+    validate_dir(&trash_dir.root);
+    validate_dir(&trash_dir.files);
+    validate_dir(&trash_dir.info);
 
-    let files_path = trash_dir.join("files");
-    validate_dir(&files_path);
+    trash_dir
+}
 
-    let info_path = trash_dir.join("info");
-    validate_dir(&info_path);
+fn find_topdir_trashes(mount_point: &Path, uid: u32) -> Vec<TrashDir> {
+    let mut trash_dirs = Vec::new();
 
-    (trash_dir, files_path, info_path)
+    let admin_trash = mount_point.join(".Trash");
+
+    if is_valid_admin_trash(&admin_trash) {
+        let trash_dir = TrashDir::new(&admin_trash.join(uid.to_string()), Some(mount_point));
+        if trash_dir.is_valid() {
+            trash_dirs.push(trash_dir);
+        }
+    }
+
+    let user_trash = TrashDir::new(
+        &mount_point.join(format!(".Trash-{}", uid)),
+        Some(mount_point),
+    );
+    if user_trash.is_valid() {
+        trash_dirs.push(user_trash);
+    }
+
+    trash_dirs
 }
 
-fn validate_dir(dir: &PathBuf) {
-    if !dir.exists() {
-        if let Err(err) = fs::create_dir(&dir) {
-            panic!("Error creating {}: {}", &dir.display(), err);
+fn get_mount_points() -> Vec<PathBuf> {
+    fs::read("/proc/self/mountinfo")
+        .map(|content| {
+            content
+                .split(|byte| *byte == b'\n')
+                .filter_map(parse_mount_info_line)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Format (see proc(5)):
+// 36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw
+// The mount point is the 5th field, the filesystem type follows the "-".
+fn parse_mount_info_line(line: &[u8]) -> Option<PathBuf> {
+    let fields = line.split(|byte| *byte == b' ').collect::<Vec<&[u8]>>();
+    let mount_point = fields.get(4)?;
+
+    let separator = fields.iter().position(|field| *field == b"-")?;
+    let fs_type = std::str::from_utf8(fields.get(separator + 1)?).ok()?;
+
+    if IGNORED_FS_TYPES.contains(&fs_type) {
+        return None;
+    }
+
+    Some(PathBuf::from(OsString::from_vec(unescape_mount_path(
+        mount_point,
+    ))))
+}
+
+// Spaces, tabs, newlines and backslashes are written as octal escapes,
+// e.g. "/mnt/my\040drive".
+fn unescape_mount_path(path: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(path.len());
+    let mut i = 0;
+
+    while i < path.len() {
+        let escaped = path
+            .get(i + 1..i + 4)
+            .filter(|_| path[i] == b'\\')
+            .and_then(|octal| std::str::from_utf8(octal).ok())
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 4;
+            }
+            None => {
+                result.push(path[i]);
+                i += 1;
+            }
         }
     }
 
+    result
+}
+
+fn current_uid() -> Option<u32> {
+    fs::metadata("/proc/self").map(|meta| meta.uid()).ok()
+}
+
+fn validate_dir(dir: &Path) {
+    if !dir.exists()
+        && let Err(err) = fs::create_dir_all(dir)
+    {
+        panic!("Error creating {}: {}", dir.display(), err);
+    }
+
     if !dir.is_dir() {
         panic!("{} not a directory", dir.display());
     }
 }
 
-pub fn list_files_from_dir(dir: &PathBuf) -> Option<Vec<PathBuf>> {
+pub fn list_files_from_dir(dir: &Path) -> Option<Vec<PathBuf>> {
     dir.read_dir().ok().and_then(|entries| {
         entries
             .map(|entry| entry.ok().map(|entry| entry.path()))
@@ -67,98 +191,1457 @@ pub fn list_files_from_dir(dir: &PathBuf) -> Option<Vec<PathBuf>> {
     })
 }
 
-pub fn empty_bin() -> Result<(), Error> {
-    let (_, files_dir, info_dir) = get_trash_dirs();
+/// Every valid entry of every trash, entries that can't be parsed are
+/// reported by the diagnostics instead.
+pub fn list_trash_entries() -> Vec<TrashEntry> {
+    get_trash_dirs().iter().flat_map(list_entries_of).collect()
+}
 
-    fs::remove_dir_all(&files_dir)?;
-    fs::create_dir(&files_dir)?;
+fn list_entries_of(trash_dir: &TrashDir) -> Vec<TrashEntry> {
+    // A trash on a flaky or unreadable mount shouldn't hide the others
+    list_files_from_dir(&trash_dir.info)
+        .unwrap_or_default()
+        .iter()
+        .filter(|file| file.extension().is_some_and(|ext| ext == "trashinfo"))
+        .filter_map(|file| TrashEntry::from_trash_info(file, trash_dir).ok())
+        .collect()
+}
 
-    fs::remove_dir_all(&info_dir)?;
-    fs::create_dir(&info_dir)?;
+/// Moves the whole content of every trash into a staging directory, orphans
+/// and broken entries included. Returns the entries that were staged, along
+/// with their staging directory, even when some trash couldn't be emptied.
+pub fn empty_bin(deleted_at: DateTime<Local>) -> (Vec<(TrashEntry, PathBuf)>, Option<Error>) {
+    empty_trash_dirs(&get_trash_dirs(), deleted_at)
+}
 
-    Ok(())
+fn empty_trash_dirs(
+    trash_dirs: &[TrashDir],
+    deleted_at: DateTime<Local>,
+) -> (Vec<(TrashEntry, PathBuf)>, Option<Error>) {
+    let mut staged = Vec::new();
+    let mut errors = Vec::new();
+
+    for trash_dir in trash_dirs {
+        match empty_trash_dir(trash_dir, deleted_at) {
+            Ok(entries) => staged.extend(entries),
+            Err(e) => errors.push(format!("{}: {}", trash_dir.root.display(), e)),
+        }
+    }
+
+    (
+        staged,
+        (!errors.is_empty()).then(|| Error::other(errors.join(", "))),
+    )
+}
+
+fn empty_trash_dir(
+    trash_dir: &TrashDir,
+    deleted_at: DateTime<Local>,
+) -> Result<Vec<(TrashEntry, PathBuf)>, Error> {
+    let entries = list_entries_of(trash_dir);
+    let stage = create_stage(trash_dir, deleted_at)?;
+
+    // Renaming over the empty directories of the stage replaces them
+    if let Err(e) = fs::rename(&trash_dir.files, &stage.files) {
+        remove_empty_stage(&stage);
+        return Err(e);
+    }
+
+    let result = fs::create_dir(&trash_dir.files)
+        .and_then(|_| fs::rename(&trash_dir.info, &stage.info))
+        .and_then(|_| {
+            fs::create_dir(&trash_dir.info).inspect_err(|_| {
+                let _ = fs::rename(&stage.info, &trash_dir.info);
+            })
+        });
+
+    // Without both directories the trash is unusable, so it's put back the
+    // way it was
+    if let Err(e) = result {
+        let _ = fs::remove_dir(&trash_dir.files);
+        let _ = fs::rename(&stage.files, &trash_dir.files);
+        remove_empty_stage(&stage);
+        return Err(e);
+    }
+
+    let _ = fs::remove_file(&trash_dir.directory_sizes);
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry, stage.root.clone()))
+        .collect())
+}
+
+/// Moves `path` into the trash of the filesystem it lives on, as described
+/// by the spec: the home trash when it's on the same device, otherwise a
+/// `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid` created on demand.
+/// Returns the location of the content inside the trash.
+pub fn put_item(path: &Path) -> Result<PathBuf, Error> {
+    let path = std::path::absolute(path)?;
+    let meta = fs::symlink_metadata(&path)?;
+    let file_name = path.file_name().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Cannot trash {}", path.display()),
+        )
+    })?;
+
+    let trash_dir = trash_dir_for(&path, meta.dev())?;
+
+    if path.starts_with(&trash_dir.root) || trash_dir.root.starts_with(&path) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Cannot trash the trash itself: {}", path.display()),
+        ));
+    }
+
+    let info = TrashInfo {
+        path: trash_dir.stored_path(&path),
+        deletion_date: Local::now(),
+        extra: Vec::new(),
+    };
+
+    let content_path = put_into(&path, &trash_dir, file_name, &info)?;
+
+    if meta.is_dir()
+        && let Ok(size) = item_size(&content_path)
+    {
+        remember_size(
+            &trash_dir,
+            content_path.file_name().unwrap_or_default(),
+            size,
+        );
+    }
+
+    Ok(content_path)
+}
+
+/// Moves a restored item back into the trash it came from, with its original
+/// location and deletion date. An item restored from inside a trashed
+/// directory goes back into it while the directory is still in the trash,
+/// otherwise it gets an entry of its own.
+pub fn retrash_item(entry: &TrashEntry, path: &Path) -> Result<(), Error> {
+    if entry.is_child()
+        && fs::symlink_metadata(&entry.info_path).is_ok()
+        && entry
+            .content_path
+            .parent()
+            .is_some_and(|parent| fs::symlink_metadata(parent).is_ok_and(|meta| meta.is_dir()))
+        && fs::symlink_metadata(&entry.content_path).is_err()
+    {
+        move_path(path, &entry.content_path, &mut |_| {})?;
+        // The directory's cached size is off by the item
+        forget_size(
+            &entry.trash_dir,
+            entry.info_path.file_stem().unwrap_or_default(),
+        );
+        return Ok(());
+    }
+
+    let info = TrashInfo {
+        path: entry.trash_dir.stored_path(&entry.restore_location),
+        deletion_date: entry.date,
+        extra: Vec::new(),
+    };
+
+    put_into(
+        path,
+        &entry.trash_dir,
+        entry.content_path.file_name().unwrap_or_default(),
+        &info,
+    )
+    .map(|_| ())
+}
+
+fn put_into(
+    path: &Path,
+    trash_dir: &TrashDir,
+    name: &OsStr,
+    info: &TrashInfo,
+) -> Result<PathBuf, Error> {
+    let (info_path, content_path) = claim_trash_name(trash_dir, name)?;
+
+    let result = fs::write(&info_path, info.to_string())
+        .and_then(|_| move_path(path, &content_path, &mut |_| {}));
+
+    if let Err(e) = result {
+        // Release the claimed name, the item stays where it was
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+
+    Ok(content_path)
+}
+
+fn trash_dir_for(path: &Path, device: u64) -> Result<TrashDir, Error> {
+    let home_trash = home_trash_dir();
+
+    if fs::metadata(&home_trash.root)?.dev() == device {
+        return Ok(home_trash);
+    }
+
+    let uid = current_uid().ok_or_else(|| Error::other("Cannot determine the user id"))?;
+    let mount_point = mount_point_for(path, device, get_mount_points())?
+        .ok_or_else(|| Error::other(format!("No mount point found for {}", path.display())))?;
+
+    if let Some(trash_dir) = find_topdir_trashes(&mount_point, uid).into_iter().next() {
+        return Ok(trash_dir);
+    }
+
+    // Prefer the admin provided $topdir/.Trash, otherwise set up our own
+    let admin_trash = mount_point.join(".Trash");
+    let candidates = [
+        TrashDir::new(&admin_trash.join(uid.to_string()), Some(&mount_point)),
+        TrashDir::new(
+            &mount_point.join(format!(".Trash-{}", uid)),
+            Some(&mount_point),
+        ),
+    ];
+
+    for trash_dir in candidates {
+        if trash_dir.root.starts_with(&admin_trash) && !is_valid_admin_trash(&admin_trash) {
+            continue;
+        }
+
+        let created = [&trash_dir.root, &trash_dir.files, &trash_dir.info]
+            .iter()
+            .try_for_each(|dir| create_private_dir(dir));
+
+        if created.is_ok() {
+            return Ok(trash_dir);
+        }
+    }
+
+    Err(Error::other(format!(
+        "Cannot create a trash directory on {}",
+        mount_point.display()
+    )))
+}
+
+// The mount point of `device` that `path` is on. The path may lead through
+// symlinks to other filesystems, it's only compared once they're resolved.
+// Of several mount points of the device (bind mounts), the deepest one
+// holding the path wins.
+fn mount_point_for(
+    path: &Path,
+    device: u64,
+    mount_points: Vec<PathBuf>,
+) -> Result<Option<PathBuf>, Error> {
+    let real_parent = fs::canonicalize(path.parent().unwrap_or(path))?;
+
+    Ok(mount_points
+        .into_iter()
+        .filter(|mount_point| fs::metadata(mount_point).is_ok_and(|meta| meta.dev() == device))
+        .max_by_key(|mount_point| {
+            (
+                real_parent.starts_with(mount_point),
+                mount_point.components().count(),
+            )
+        }))
+}
+
+fn create_private_dir(dir: &Path) -> Result<(), Error> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists && dir.is_dir() => Ok(()),
+        result => result,
+    }
+}
+
+// Finds a free name in the trash, "video.avi", then "video_2.avi" and so on.
+// The info file is created with O_EXCL, which makes the claim atomic even
+// with several processes trashing at once.
+fn claim_trash_name(trash_dir: &TrashDir, file_name: &OsStr) -> Result<(PathBuf, PathBuf), Error> {
+    for n in 1.. {
+        let name = unique_name(file_name, n);
+        let info_path = trash_dir.info_path_for(&name);
+        let content_path = trash_dir.files.join(&name);
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            // An orphan can still hold the name in files/
+            Ok(_) if fs::symlink_metadata(&content_path).is_ok() => {
+                fs::remove_file(&info_path)?;
+            }
+            Ok(_) => return Ok((info_path, content_path)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!()
+}
+
+/// Name used for the `n`-th item with the same name, see
+/// `strip_collision_suffix` for the reverse.
+fn unique_name(name: &OsStr, n: usize) -> OsString {
+    if n == 1 {
+        return name.to_os_string();
+    }
+
+    let bytes = name.as_bytes();
+    let stem_end = bytes
+        .iter()
+        .rposition(|byte| *byte == b'.')
+        .filter(|index| *index > 0)
+        .unwrap_or(bytes.len());
+    let (stem, extension) = bytes.split_at(stem_end);
+
+    OsString::from_vec([stem, format!("_{}", n).as_bytes(), extension].concat())
+}
+
+// $topdir/.Trash must be a real directory with the sticky bit set, otherwise
+// the spec says it has to be ignored.
+fn is_valid_admin_trash(admin_trash: &Path) -> bool {
+    fs::symlink_metadata(admin_trash)
+        .map(|meta| meta.is_dir() && meta.permissions().mode() & 0o1000 != 0)
+        .unwrap_or(false)
 }
 
-pub fn restore_item(item: &TrashEntry) -> Result<(), Error> {
-    if !item.content_path.exists() {
+/// Moves the item to `target`, nothing existing at `target` is replaced.
+pub fn restore_item_to(
+    item: &TrashEntry,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    check_content_exists(item)?;
+
+    move_path(&item.content_path, target, progress)?;
+
+    remove_info(item)
+}
+
+/// Copies the item to `target`, the trash is left as it is. Unlike a
+/// restore, nothing is ever overwritten.
+pub fn extract_item(
+    item: &TrashEntry,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    check_content_exists(item)?;
+
+    // Spares copying for nothing, the copy itself never replaces the target
+    // either, whatever appears there meanwhile
+    if fs::symlink_metadata(target).is_ok() {
+        return Err(already_exists(target));
+    }
+
+    copy_path(&item.content_path, target, progress)
+}
+
+/// Like [`restore_item_to`], but an existing directory at `target` is
+/// replaced too. The old target is only removed once the item is in place.
+pub fn restore_item_replacing(
+    item: &TrashEntry,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    check_content_exists(item)?;
+
+    replace_path(&item.content_path, target, progress)?;
+
+    remove_info(item)
+}
+
+/// Moves the contents of a trashed directory into the existing directory at
+/// `target`. Files present in both are replaced by the trashed ones. A merge
+/// that fails halfway isn't rolled back, the error tells what was moved.
+pub fn restore_item_merging(
+    item: &TrashEntry,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    check_content_exists(item)?;
+
+    let mut merged = Vec::new();
+    merge_dirs(&item.content_path, target, &mut merged, progress)
+        .map_err(|e| merge_error(e, target, &merged))?;
+
+    remove_info(item)
+}
+
+// E.g. `File name too long, already merged into /home/user/project: a.txt,
+// src/main.rs and 3 more`
+fn merge_error(error: Error, target: &Path, merged: &[PathBuf]) -> Error {
+    const SHOWN: usize = 3;
+
+    if merged.is_empty() {
+        return error;
+    }
+
+    let mut names = merged
+        .iter()
+        .take(SHOWN)
+        .map(|path| {
+            path.strip_prefix(target)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    if merged.len() > SHOWN {
+        names.push_str(&format!(" and {} more", merged.len() - SHOWN));
+    }
+
+    Error::new(
+        error.kind(),
+        format!(
+            "{}, already merged into {}: {}",
+            error,
+            target.display(),
+            names
+        ),
+    )
+}
+
+// Children of a trashed directory share its info file, which stays as long
+// as the rest of the directory is in the trash. Its cached size is out of
+// date either way.
+fn remove_info(item: &TrashEntry) -> Result<(), Error> {
+    let result = match item.is_child() {
+        true => Ok(()),
+        false => remove_path(&item.info_path),
+    };
+
+    forget_size(
+        &item.trash_dir,
+        item.info_path.file_stem().unwrap_or_default(),
+    );
+
+    result
+}
+
+/// The first of `path`, `path_2`, `path_3`... that doesn't exist yet.
+pub fn free_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+
+    (1..)
+        .map(|n| path.with_file_name(unique_name(name, n)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .unwrap()
+}
+
+// A free `.<name>.<suffix>` next to `path`
+fn hidden_sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(suffix);
+
+    free_path(&path.with_file_name(name))
+}
+
+fn check_content_exists(item: &TrashEntry) -> Result<(), Error> {
+    if fs::symlink_metadata(&item.content_path).is_err() {
         return Err(Error::new(
-            std::io::ErrorKind::NotFound,
+            ErrorKind::NotFound,
             format!(
                 "File not found: {}, name: {}, restore location: {}",
                 item.content_path.display(),
-                item.display_name,
+                item.display_name.to_string_lossy(),
                 item.restore_location.display(),
             ),
         ));
     }
 
-    fs::rename(&item.content_path, &item.restore_location)?;
+    Ok(())
+}
+
+fn replace_path(
+    source: &Path,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    if fs::symlink_metadata(target).is_err() {
+        return move_path(source, target, progress);
+    }
+
+    let backup = hidden_sibling(target, "trash-tui-backup");
+
+    fs::rename(target, &backup)?;
+
+    if let Err(e) = move_path(source, target, progress) {
+        // Put the original back, nothing was lost
+        let _ = fs::rename(&backup, target);
+        return Err(e);
+    }
+
+    remove_path(&backup)
+}
+
+// Paths moved into `target` are added to `merged`, as they're moved
+fn merge_dirs(
+    source: &Path,
+    target: &Path,
+    merged: &mut Vec<PathBuf>,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    for entry in fs::read_dir(source)? {
+        let source_child = entry?.path();
+        let target_child = target.join(source_child.file_name().unwrap_or_default());
+
+        let is_source_dir = fs::symlink_metadata(&source_child)?.is_dir();
+
+        match fs::symlink_metadata(&target_child) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                move_path(&source_child, &target_child, progress)?
+            }
+            Ok(meta) if meta.is_dir() && is_source_dir => {
+                merge_dirs(&source_child, &target_child, merged, progress)?;
+                continue;
+            }
+            _ => replace_path(&source_child, &target_child, progress)?,
+        }
+
+        merged.push(target_child);
+    }
+
+    fs::remove_dir(source)
+}
+
+/// Bytes copied so far when a move has to copy between filesystems.
+#[derive(Clone, Copy)]
+pub struct Progress {
+    pub copied: u64,
+    pub total: u64,
+}
+
+/// Renames `source` to `target`, unless something exists there. Across
+/// filesystems (`EXDEV`) it's copied instead, with its permissions, timestamps and xattrs where possible. A
+/// failed copy is removed again and `source` is only deleted once the copy
+/// is complete.
+pub fn move_path(
+    source: &Path,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    match rename_no_replace(source, target) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {}
+        result => return result,
+    }
+
+    copy_path(source, target, progress)?;
 
-    if item.info_path.exists() {
-        fs::remove_file(&item.info_path)?;
+    remove_path(source).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!(
+                "Copied to {}, but cannot remove {}: {}",
+                target.display(),
+                source.display(),
+                e
+            ),
+        )
+    })
+}
+
+/// Recursively copies `source` to `target`, see [`move_path`] for what's
+/// kept. A failed copy is removed again.
+pub fn copy_path(
+    source: &Path,
+    target: &Path,
+    progress: &mut dyn FnMut(Progress),
+) -> Result<(), Error> {
+    let total = copy_size(source)?;
+    let mut copied = 0;
+
+    // A partial copy must never be taken for the real thing, so it only
+    // gets the target name once it's complete
+    let partial = hidden_sibling(target, "trash-tui-partial");
+    let result = copy_tree(source, &partial, &mut |bytes| {
+        copied += bytes;
+        progress(Progress { copied, total });
+    })
+    .and_then(|_| rename_no_replace(&partial, target));
+
+    if let Err(e) = result {
+        let _ = remove_path(&partial);
+        return Err(e);
     }
 
     Ok(())
 }
 
-pub fn delete_item(item: &TrashEntry) -> Result<(), Error> {
-    // first handle the content - if it breaks there won't be a dangling info file
-    if item.content_path.exists() {
-        if item.content_path.is_dir() {
-            fs::remove_dir_all(&item.content_path)?;
-        } else {
-            fs::remove_file(&item.content_path)?;
+// Renames `source` to `target` unless something exists there, a plain
+// rename would replace it
+fn rename_no_replace(source: &Path, target: &Path) -> Result<(), Error> {
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes()).map_err(|_| Error::from(ErrorKind::InvalidInput))
+    };
+    let (c_source, c_target) = (c_path(source)?, c_path(target)?);
+
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            c_source.as_ptr(),
+            libc::AT_FDCWD,
+            c_target.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+
+    let e = Error::last_os_error();
+    match e.raw_os_error() {
+        // The kernel or the filesystem doesn't know the flag
+        Some(libc::EINVAL | libc::ENOSYS | libc::EOPNOTSUPP) => rename_claiming(source, target),
+        _ if e.kind() == ErrorKind::AlreadyExists => Err(already_exists(target)),
+        _ => Err(e),
+    }
+}
+
+// Claims the target name with an empty file or directory first, which the
+// rename then replaces. Only that empty placeholder can ever be replaced.
+fn rename_claiming(source: &Path, target: &Path) -> Result<(), Error> {
+    let is_dir = fs::symlink_metadata(source)?.is_dir();
+    let claimed = match is_dir {
+        true => fs::create_dir(target),
+        false => OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(target)
+            .map(|_| ()),
+    };
+    claimed.map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => already_exists(target),
+        _ => e,
+    })?;
+
+    fs::rename(source, target).map_err(|e| {
+        let _ = match is_dir {
+            true => fs::remove_dir(target),
+            false => fs::remove_file(target),
+        };
+        match e.kind() {
+            ErrorKind::DirectoryNotEmpty => already_exists(target),
+            _ => e,
         }
+    })
+}
+
+fn already_exists(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+fn copy_tree(source: &Path, target: &Path, copied: &mut dyn FnMut(u64)) -> Result<(), Error> {
+    let meta = fs::symlink_metadata(source)?;
+    let file_type = meta.file_type();
+
+    if file_type.is_symlink() {
+        symlink(fs::read_link(source)?, target)?;
+    } else if file_type.is_dir() {
+        // The real permissions are set once the content is copied, the
+        // directory may well be read-only
+        DirBuilder::new().mode(0o700).create(target)?;
+
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &target.join(entry.file_name()), copied)?;
+        }
+    } else if file_type.is_file() {
+        copy_file(source, target, copied)?;
+    } else {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Cannot copy special file {}", source.display()),
+        ));
+    }
+
+    copy_metadata(source, target, &meta);
+
+    Ok(())
+}
+
+fn copy_file(source: &Path, target: &Path, copied: &mut dyn FnMut(u64)) -> Result<(), Error> {
+    let mut reader = File::open(source)?;
+    let mut writer = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(target)?;
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        let bytes = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buffer[..bytes])?;
+        copied(bytes as u64);
     }
 
-    if item.info_path.exists() {
-        fs::remove_file(&item.info_path)?;
+    writer.sync_all()
+}
+
+// Best effort: a filesystem without xattrs or a file owned by someone else
+// shouldn't fail the restore.
+fn copy_metadata(source: &Path, target: &Path, meta: &Metadata) {
+    for name in xattr::list(source).into_iter().flatten() {
+        if let Ok(Some(value)) = xattr::get(source, &name) {
+            let _ = xattr::set(target, &name, &value);
+        }
+    }
+
+    let _ = lchown(target, Some(meta.uid()), Some(meta.gid()));
+
+    // Symlinks have neither permissions nor settable times without libc
+    if meta.file_type().is_symlink() {
+        return;
+    }
+
+    if let (Ok(accessed), Ok(modified), Ok(file)) =
+        (meta.accessed(), meta.modified(), File::open(target))
+    {
+        let _ = file.set_times(
+            FileTimes::new()
+                .set_accessed(accessed)
+                .set_modified(modified),
+        );
     }
 
+    let _ = fs::set_permissions(target, meta.permissions());
+}
+
+/// Moves the item into a new staging directory of its trash instead of
+/// deleting it, see [`unstage_item`] and [`purge_expired`]. Returns the
+/// staging directory.
+pub fn stage_item(item: &TrashEntry, deleted_at: DateTime<Local>) -> Result<PathBuf, Error> {
+    let stage = create_stage(&item.trash_dir, deleted_at)?;
+    let name = item.content_path.file_name().unwrap_or_default();
+    let staged_info = stage
+        .info
+        .join(item.info_path.file_name().unwrap_or_default());
+
+    let has_content = fs::symlink_metadata(&item.content_path).is_ok();
+    let has_info = fs::symlink_metadata(&item.info_path).is_ok();
+
+    // Dangling entries are deleted too, and orphans, which have no info
+    let result = match (has_content, has_info) {
+        (false, false) => Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} is gone from the trash", name.to_string_lossy()),
+        )),
+        (true, _) => fs::rename(&item.content_path, stage.files.join(name)),
+        (false, true) => Ok(()),
+    }
+    .and_then(|_| match has_info {
+        true => fs::rename(&item.info_path, &staged_info).inspect_err(|_| {
+            let _ = fs::rename(stage.files.join(name), &item.content_path);
+        }),
+        false => Ok(()),
+    });
+
+    if let Err(e) = result {
+        remove_empty_stage(&stage);
+        return Err(e);
+    }
+
+    forget_size(&item.trash_dir, name);
+
+    Ok(stage.root)
+}
+
+/// Puts a staged item back into its trash, under another name if its old
+/// one was taken in the meantime.
+pub fn unstage_item(stage: &Path, item: &TrashEntry) -> Result<(), Error> {
+    let staged = TrashDir::new(stage, None);
+    let name = item.content_path.file_name().unwrap_or_default();
+    let staged_info = staged
+        .info
+        .join(item.info_path.file_name().unwrap_or_default());
+    let staged_content = staged.files.join(name);
+
+    let has_info = fs::symlink_metadata(&staged_info).is_ok();
+    if !has_info && fs::symlink_metadata(&staged_content).is_err() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} was already purged", name.to_string_lossy()),
+        ));
+    }
+
+    // Orphans and stray files of `info/` can't claim a name, they go back
+    // where they were
+    if !has_info || item.info_path != item.trash_dir.info_path_for(name) {
+        unstage_as_it_was(item, &staged_content, &staged_info)?;
+        remove_empty_stage(&staged);
+        return Ok(());
+    }
+
+    let (info_path, content_path) = claim_trash_name(&item.trash_dir, name)?;
+
+    let result = match fs::symlink_metadata(&staged_content) {
+        Ok(_) => fs::rename(&staged_content, &content_path),
+        Err(_) => Ok(()),
+    }
+    .and_then(|_| fs::rename(&staged_info, &info_path));
+
+    if let Err(e) = result {
+        let _ = fs::rename(&content_path, &staged_content);
+        let _ = fs::remove_file(&info_path);
+        return Err(e);
+    }
+
+    // The stage goes once everything in it is back
+    remove_empty_stage(&staged);
+
     Ok(())
 }
 
-pub fn parse_line(lines: &mut Lines<BufReader<File>>, path: &PathBuf) -> Result<String, Message> {
-    match lines.next() {
-        Some(Ok(line)) => Ok(line),
-        Some(Err(e)) => Err(Message::error(format!(
-            "Error reading trash info file: {} - {}",
-            path.to_string_lossy(),
-            e
-        ))),
-        None => Err(Message::error(format!(
-            "No lines found in trash info file: {}",
-            path.to_string_lossy()
-        ))),
+fn unstage_as_it_was(
+    item: &TrashEntry,
+    staged_content: &Path,
+    staged_info: &Path,
+) -> Result<(), Error> {
+    let has_content = fs::symlink_metadata(staged_content).is_ok();
+    if has_content {
+        rename_no_replace(staged_content, &item.content_path)?;
+    }
+
+    match fs::symlink_metadata(staged_info) {
+        Ok(_) => rename_no_replace(staged_info, &item.info_path).inspect_err(|_| {
+            if has_content {
+                let _ = fs::rename(&item.content_path, staged_content);
+            }
+        }),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Every staged item, with the staging directory it's in and the time it
+/// was deleted. The entries point to where they were before the delete.
+pub fn list_staged() -> Vec<(TrashEntry, PathBuf, DateTime<Local>)> {
+    let mut staged = Vec::new();
+
+    for trash_dir in get_trash_dirs() {
+        for stage in list_files_from_dir(&trash_dir.staging).unwrap_or_default() {
+            let Some(deleted_at) = stage_time(&stage) else {
+                continue;
+            };
+            let stage_dir = TrashDir::new(&stage, trash_dir.topdir.as_deref());
+
+            for info_path in list_files_from_dir(&stage_dir.info).unwrap_or_default() {
+                let Ok(mut entry) = TrashEntry::from_trash_info(&info_path, &stage_dir) else {
+                    continue;
+                };
+                let name = info_path.file_stem().unwrap_or_default();
+
+                entry.info_path = trash_dir.info_path_for(name);
+                entry.content_path = trash_dir.files.join(name);
+                entry.trash_dir = trash_dir.clone();
+
+                staged.push((entry, stage.clone(), deleted_at));
+            }
+        }
+    }
+
+    staged
+}
+
+/// Permanently deletes the staged items deleted more than `grace_period`
+/// ago, or all of them without a grace period. A stage that can't be removed
+/// doesn't keep the others from being purged.
+pub fn purge_expired(grace_period: Option<TimeDelta>) -> Result<(), Error> {
+    let now = Local::now();
+    let errors = get_trash_dirs()
+        .iter()
+        .flat_map(|trash_dir| purge_stages(trash_dir, grace_period, now))
+        .map(|e| e.to_string())
+        .collect::<Vec<String>>();
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(Error::other(errors.join(", "))),
+    }
+}
+
+fn purge_stages(
+    trash_dir: &TrashDir,
+    grace_period: Option<TimeDelta>,
+    now: DateTime<Local>,
+) -> Vec<Error> {
+    list_files_from_dir(&trash_dir.staging)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|stage| match (grace_period, stage_time(stage)) {
+            (None, _) => true,
+            (Some(grace_period), Some(deleted_at)) => deleted_at + grace_period <= now,
+            // Not a stage of ours, only an explicit purge removes it
+            (Some(_), None) => false,
+        })
+        .filter_map(|stage| {
+            remove_path(&stage)
+                .err()
+                .map(|e| Error::new(e.kind(), format!("{}: {}", stage.display(), e)))
+        })
+        .collect()
+}
+
+// A new `<staging>/<deleted_at>-<n>` directory, laid out like a trash with
+// `files/` and `info/`
+fn create_stage(trash_dir: &TrashDir, deleted_at: DateTime<Local>) -> Result<TrashDir, Error> {
+    create_private_dir(&trash_dir.staging)?;
+
+    let root = (1..)
+        .map(|n| {
+            let dir = trash_dir
+                .staging
+                .join(format!("{}-{}", deleted_at.timestamp(), n));
+            DirBuilder::new().mode(0o700).create(&dir).map(|_| dir)
+        })
+        .find(|result| !matches!(result, Err(e) if e.kind() == ErrorKind::AlreadyExists))
+        .unwrap()?;
+
+    let stage = TrashDir::new(&root, None);
+    fs::create_dir(&stage.files)?;
+    fs::create_dir(&stage.info)?;
+
+    Ok(stage)
+}
+
+// Whatever is still in the stage stays, `remove_dir` fails on anything that
+// isn't empty
+fn remove_empty_stage(stage: &TrashDir) {
+    let _ = fs::remove_dir(&stage.files);
+    let _ = fs::remove_dir(&stage.info);
+    let _ = fs::remove_dir(&stage.root);
+}
+
+fn stage_time(stage: &Path) -> Option<DateTime<Local>> {
+    let name = stage.file_name()?.to_str()?;
+    let (timestamp, _) = name.split_once('-')?;
+
+    Local.timestamp_opt(timestamp.parse().ok()?, 0).single()
+}
+
+// The bytes a copy has to write, which is what the progress counts. Sizes
+// shown anywhere are `sizes::item_size`, the disk space used.
+fn copy_size(path: &Path) -> Result<u64, Error> {
+    let meta = fs::symlink_metadata(path)?;
+
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+
+    fs::read_dir(path)?.try_fold(0, |size, entry| Ok(size + copy_size(&entry?.path())?))
+}
+
+/// Removes a file, symlink or directory tree; a missing path is not an error.
+pub fn remove_path(path: &Path) -> Result<(), Error> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stages the files of the diagnostic like [`stage_item`] does, so they're
+/// only purged after the grace period. Returns the entry and stage to undo
+/// the delete with, the entry points to the files as they were.
+pub fn delete_diagnostic(
+    diagnostic: &Diagnostic,
+    deleted_at: DateTime<Local>,
+) -> Result<(TrashEntry, PathBuf), Error> {
+    let trash_dir = &diagnostic.trash_dir;
+    let content_path = diagnostic.content_path().unwrap_or_else(|| {
+        // A stray file of `info/` stands for itself
+        trash_dir
+            .files
+            .join(diagnostic.path.file_name().unwrap_or_default())
+    });
+    let info_path = match diagnostic.problem {
+        Problem::OrphanedContent => {
+            trash_dir.info_path_for(content_path.file_name().unwrap_or_default())
+        }
+        _ => diagnostic.path.clone(),
+    };
+
+    let entry = TrashEntry {
+        display_name: content_path.file_name().unwrap_or_default().to_os_string(),
+        restore_location: guess_original_path(&content_path, trash_dir),
+        info_path,
+        content_path,
+        date: deleted_at,
+        trash_dir: trash_dir.clone(),
+    };
+    let stage = stage_item(&entry, deleted_at)?;
+
+    Ok((entry, stage))
+}
+
+pub fn fix_diagnostic(diagnostic: &Diagnostic) -> Result<(), Error> {
+    match &diagnostic.problem {
+        // Nothing to restore, the info file is just noise
+        Problem::DanglingInfo => remove_path(&diagnostic.path),
+        Problem::OrphanedContent => adopt_orphan(
+            &diagnostic.path,
+            &diagnostic.trash_dir,
+            &guess_original_path(&diagnostic.path, &diagnostic.trash_dir),
+        ),
+        Problem::InvalidInfo(_) => {
+            let Some(content_path) = diagnostic.content_path() else {
+                return remove_path(&diagnostic.path);
+            };
+
+            let info = TrashInfo::salvage(
+                &fs::read(&diagnostic.path)?,
+                &diagnostic
+                    .trash_dir
+                    .stored_path(&guess_original_path(&content_path, &diagnostic.trash_dir)),
+                modification_date(&content_path).unwrap_or_else(|_| Local::now()),
+            );
+
+            write_trash_info(&diagnostic.path, &info)
+        }
+    }
+}
+
+/// Generates the missing trashinfo for a file in `files/`, which turns it
+/// into a regular trash entry. The deletion date is unknown, the file's
+/// modification time is the closest thing to it.
+pub fn adopt_orphan(
+    content_path: &Path,
+    trash_dir: &TrashDir,
+    original_path: &Path,
+) -> Result<(), Error> {
+    if !original_path.is_absolute() || original_path.file_name().is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Not an absolute file path: {}", original_path.display()),
+        ));
+    }
+
+    let info = TrashInfo {
+        path: trash_dir.stored_path(original_path),
+        deletion_date: modification_date(content_path)?,
+        extra: Vec::new(),
+    };
+
+    let mut info_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(trash_dir.info_path_for(content_path.file_name().unwrap_or_default()))?;
+
+    info_file.write_all(info.to_string().as_bytes())
+}
+
+fn modification_date(path: &Path) -> Result<DateTime<Local>, Error> {
+    Ok(fs::symlink_metadata(path)?.modified()?.into())
+}
+
+// Replaces the file atomically so a crash can't leave it half written.
+fn write_trash_info(info_path: &Path, info: &TrashInfo) -> Result<(), Error> {
+    let mut tmp_name = info_path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = info_path.with_file_name(tmp_name);
+
+    fs::write(&tmp_path, info.to_string())?;
+    fs::rename(&tmp_path, info_path)
+}
+
+/// Without any metadata the best guess is the top directory of the trash,
+/// or the home directory for the home trash. A `_N` suffix is dropped when
+/// it was added on a name collision, that is when `video.avi` is in the trash
+/// next to `video_2.avi`. Names like `IMG_0001.JPG` are kept.
+pub fn guess_original_path(content_path: &Path, trash_dir: &TrashDir) -> PathBuf {
+    let parent = trash_dir
+        .topdir
+        .clone()
+        .or_else(|| env::var("HOME").ok().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("/"));
+
+    let name = content_path.file_name().unwrap_or_default();
+    let name = strip_collision_suffix(name)
+        .filter(|original| fs::symlink_metadata(trash_dir.files.join(original)).is_ok())
+        .unwrap_or_else(|| name.to_os_string());
+
+    parent.join(name)
+}
+
+// "video_2.avi" -> "video.avi", `None` without a `_N` suffix
+fn strip_collision_suffix(name: &OsStr) -> Option<OsString> {
+    let bytes = name.as_bytes();
+    // "video_2.avi" -> stem "video_2", extension ".avi"
+    let stem_end = bytes
+        .iter()
+        .rposition(|byte| *byte == b'.')
+        .filter(|index| *index > 0)
+        .unwrap_or(bytes.len());
+    let (stem, extension) = bytes.split_at(stem_end);
+
+    match stem.iter().rposition(|byte| *byte == b'_') {
+        Some(index)
+            if index > 0
+                && index + 1 < stem.len()
+                && stem[index + 1..].iter().all(u8::is_ascii_digit) =>
+        {
+            Some(OsString::from_vec([&stem[..index], extension].concat()))
+        }
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use chrono::Local;
-
     use super::*;
+    use crate::test_utils::{TempDir, trash_dir, trash_entry, trash_file};
 
     #[test]
     fn can_find_trash_dirs() {
         let result = get_trash_dirs();
+        let home_trash = PathBuf::from(env::var("HOME").unwrap())
+            .join(".local")
+            .join("share")
+            .join("Trash");
+
         assert_eq!(
-            result,
-            (
-                PathBuf::from(env::var("HOME").unwrap())
-                    .join(".local")
-                    .join("share")
-                    .join("Trash"),
-                PathBuf::from(env::var("HOME").unwrap())
-                    .join(".local")
-                    .join("share")
-                    .join("Trash")
-                    .join("files"),
-                PathBuf::from(env::var("HOME").unwrap())
-                    .join(".local")
-                    .join("share")
-                    .join("Trash")
-                    .join("info")
+            result.first(),
+            Some(&TrashDir {
+                root: home_trash.clone(),
+                files: home_trash.join("files"),
+                info: home_trash.join("info"),
+                staging: home_trash.join(".staging"),
+                directory_sizes: home_trash.join("directorysizes"),
+                topdir: None,
+            })
+        );
+    }
+
+    #[test]
+    fn can_parse_mount_info() {
+        assert_eq!(
+            parse_mount_info_line(
+                b"36 35 98:0 / /mnt/my\\040drive rw,noatime master:1 - ext4 /dev/sdb1 rw"
+            ),
+            Some(PathBuf::from("/mnt/my drive"))
+        );
+        assert_eq!(
+            parse_mount_info_line(b"22 28 0:21 / /proc rw,nosuid shared:13 - proc proc rw"),
+            None
+        );
+        assert_eq!(parse_mount_info_line(b""), None);
+    }
+
+    #[test]
+    fn can_strip_collision_suffix() {
+        let strip = |name: &str| strip_collision_suffix(OsStr::new(name));
+
+        assert_eq!(strip("video_2.avi"), Some(OsString::from("video.avi")));
+        assert_eq!(strip("notes_12"), Some(OsString::from("notes")));
+        assert_eq!(strip("my_file.txt"), None);
+        assert_eq!(strip("_2.txt"), None);
+        assert_eq!(strip(".bashrc_2"), Some(OsString::from(".bashrc")));
+    }
+
+    #[test]
+    fn can_adopt_orphan_with_guessed_path() {
+        let root = TempDir::new("adopt");
+        let trash_dir = TrashDir::new(&root.join(".Trash-1000"), Some(&root));
+        fs::create_dir_all(&trash_dir.files).unwrap();
+        fs::create_dir_all(&trash_dir.info).unwrap();
+        for name in ["IMG_0001.JPG", "photo_2024.jpg", "video.avi", "video_2.avi"] {
+            fs::write(trash_dir.files.join(name), "").unwrap();
+        }
+
+        let guess = |name: &str| guess_original_path(&trash_dir.files.join(name), &trash_dir);
+        assert_eq!(guess("IMG_0001.JPG"), root.join("IMG_0001.JPG"));
+        assert_eq!(guess("photo_2024.jpg"), root.join("photo_2024.jpg"));
+        assert_eq!(guess("video_2.avi"), root.join("video.avi"));
+
+        let content_path = trash_dir.files.join("IMG_0001.JPG");
+        adopt_orphan(&content_path, &trash_dir, &guess("IMG_0001.JPG")).unwrap();
+        let entry = TrashEntry::from_trash_info(
+            &trash_dir.info_path_for("IMG_0001.JPG".as_ref()),
+            &trash_dir,
+        )
+        .unwrap();
+        assert_eq!(entry.restore_location, root.join("IMG_0001.JPG"));
+        assert_eq!(
+            entry.date.timestamp(),
+            modification_date(&content_path).unwrap().timestamp()
+        );
+
+        // The name is taken now
+        assert!(adopt_orphan(&content_path, &trash_dir, &root.join("other.jpg")).is_err());
+    }
+
+    #[test]
+    fn can_make_unique_name() {
+        assert_eq!(unique_name(OsStr::new("video.avi"), 1), "video.avi");
+        assert_eq!(unique_name(OsStr::new("video.avi"), 2), "video_2.avi");
+        assert_eq!(unique_name(OsStr::new(".bashrc"), 3), ".bashrc_3");
+        assert_eq!(
+            strip_collision_suffix(&unique_name(OsStr::new("a.tar.gz"), 4)),
+            Some(OsString::from("a.tar.gz"))
+        );
+    }
+
+    #[test]
+    fn can_find_mount_point_through_symlinks() {
+        let root = TempDir::new("mount");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        symlink(root.join("a"), root.join("b/link")).unwrap();
+        let device = fs::metadata(&*root).unwrap().dev();
+
+        let mount_points = vec![root.join("a"), root.join("b"), root.join("missing")];
+        assert_eq!(
+            mount_point_for(&root.join("b/link/file"), device, mount_points.clone()).unwrap(),
+            Some(root.join("a"))
+        );
+        assert_eq!(
+            mount_point_for(&root.join("b/link/file"), device + 1, mount_points).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn can_put_items_with_colliding_names() {
+        let root = TempDir::new("put");
+        let trash_dir = trash_dir(&root.join("trash"));
+        // An orphan holds the name without an info file
+        fs::write(trash_dir.files.join("notes_2.txt"), "").unwrap();
+
+        let mut content_paths = Vec::new();
+        for content in ["first", "second", "third"] {
+            fs::write(root.join("notes.txt"), content).unwrap();
+            let info = TrashInfo {
+                path: root.join("notes.txt"),
+                deletion_date: Local::now(),
+                extra: Vec::new(),
+            };
+            content_paths.push(
+                put_into(
+                    &root.join("notes.txt"),
+                    &trash_dir,
+                    "notes.txt".as_ref(),
+                    &info,
+                )
+                .unwrap(),
+            );
+        }
+
+        assert_eq!(
+            content_paths,
+            ["notes.txt", "notes_3.txt", "notes_4.txt"].map(|name| trash_dir.files.join(name))
+        );
+        assert!(!root.join("notes.txt").exists());
+        assert_eq!(fs::read_to_string(&content_paths[2]).unwrap(), "third");
+        let entry = TrashEntry::from_trash_info(
+            &trash_dir.info_path_for("notes_3.txt".as_ref()),
+            &trash_dir,
+        )
+        .unwrap();
+        assert_eq!(entry.restore_location, root.join("notes.txt"));
+
+        // A failed move releases the claimed name
+        let info = TrashInfo {
+            path: root.join("missing.txt"),
+            deletion_date: Local::now(),
+            extra: Vec::new(),
+        };
+        assert!(
+            put_into(
+                &root.join("missing.txt"),
+                &trash_dir,
+                "missing.txt".as_ref(),
+                &info
+            )
+            .is_err()
+        );
+        assert!(!trash_dir.info_path_for("missing.txt".as_ref()).exists());
+        let (info_path, _) = claim_trash_name(&trash_dir, "missing.txt".as_ref()).unwrap();
+        assert_eq!(info_path, trash_dir.info_path_for("missing.txt".as_ref()));
+    }
+
+    #[test]
+    fn can_copy_tree() {
+        let root = TempDir::new("copy");
+        let source = root.join("source");
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("nested/file.txt"), "content").unwrap();
+        fs::set_permissions(
+            source.join("nested/file.txt"),
+            fs::Permissions::from_mode(0o640),
+        )
+        .unwrap();
+        symlink("nested/file.txt", source.join("link")).unwrap();
+
+        let mut copied = 0;
+        copy_tree(&source, &root.join("copy"), &mut |bytes| copied += bytes).unwrap();
+
+        let file = root.join("copy/nested/file.txt");
+        assert_eq!(copied, "content".len() as u64);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "content");
+        assert_eq!(fs::metadata(&file).unwrap().mode() & 0o777, 0o640);
+        assert_eq!(
+            fs::metadata(&file).unwrap().modified().unwrap(),
+            fs::metadata(source.join("nested/file.txt"))
+                .unwrap()
+                .modified()
+                .unwrap()
+        );
+        assert_eq!(
+            fs::read_link(root.join("copy/link")).unwrap(),
+            PathBuf::from("nested/file.txt")
+        );
+    }
+
+    #[test]
+    fn cannot_restore_over_existing_target() {
+        let root = TempDir::new("restore-over");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let entry = trash_file(&trash_dir, "a.txt", &root.join("a.txt"));
+        fs::write(root.join("a.txt"), "existing").unwrap();
+
+        let error = restore_item_to(&entry, &entry.restore_location, &mut |_| {}).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "existing");
+        assert!(entry.content_path.exists());
+        assert!(entry.info_path.exists());
+    }
+
+    #[test]
+    fn cannot_copy_over_existing_target() {
+        let root = TempDir::new("copy-over");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file.txt"), "new").unwrap();
+        fs::create_dir_all(root.join("taken")).unwrap();
+        fs::write(root.join("taken/file.txt"), "old").unwrap();
+        fs::write(root.join("taken.txt"), "old").unwrap();
+
+        // The target appears after any check, only the final rename sees it
+        let error = copy_path(&root.join("dir"), &root.join("taken"), &mut |_| {}).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        let error = copy_path(
+            &root.join("dir/file.txt"),
+            &root.join("taken.txt"),
+            &mut |_| {},
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+
+        assert_eq!(
+            fs::read_to_string(root.join("taken/file.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(fs::read_to_string(root.join("taken.txt")).unwrap(), "old");
+        // Nothing partial is left behind
+        assert_eq!(fs::read_dir(&*root).unwrap().count(), 3);
+
+        copy_path(&root.join("dir"), &root.join("free"), &mut |_| {}).unwrap();
+        copy_path(
+            &root.join("dir/file.txt"),
+            &root.join("free.txt"),
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("free/file.txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(fs::read_to_string(root.join("free.txt")).unwrap(), "new");
+        assert_eq!(fs::read_dir(&*root).unwrap().count(), 5);
+    }
+
+    // What filesystems without `RENAME_NOREPLACE` get
+    #[test]
+    fn cannot_rename_claiming_over_existing_target() {
+        let root = TempDir::new("rename-claiming");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file.txt"), "new").unwrap();
+        fs::create_dir_all(root.join("taken")).unwrap();
+        fs::write(root.join("taken/file.txt"), "old").unwrap();
+        fs::write(root.join("taken.txt"), "old").unwrap();
+        fs::write(root.join("file.txt"), "new").unwrap();
+        symlink("file.txt", root.join("link")).unwrap();
+
+        for (source, target) in [
+            ("dir", "taken"),
+            ("file.txt", "taken.txt"),
+            ("link", "taken.txt"),
+        ] {
+            let error = rename_claiming(&root.join(source), &root.join(target)).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+            assert!(fs::symlink_metadata(root.join(source)).is_ok());
+        }
+        assert_eq!(
+            fs::read_to_string(root.join("taken/file.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(fs::read_to_string(root.join("taken.txt")).unwrap(), "old");
+
+        rename_claiming(&root.join("dir"), &root.join("free")).unwrap();
+        rename_claiming(&root.join("file.txt"), &root.join("free.txt")).unwrap();
+        rename_claiming(&root.join("link"), &root.join("free-link")).unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("free/file.txt")).unwrap(),
+            "new"
+        );
+        assert_eq!(fs::read_to_string(root.join("free.txt")).unwrap(), "new");
+        assert_eq!(
+            fs::read_link(root.join("free-link")).unwrap(),
+            Path::new("file.txt")
+        );
+        assert_eq!(fs::read_dir(&*root).unwrap().count(), 5);
+    }
+
+    #[test]
+    fn can_report_partial_merge() {
+        let root = TempDir::new("merge");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let item = trash_file(&trash_dir, "project", &root.join("project"));
+        fs::remove_file(&item.content_path).unwrap();
+        fs::create_dir_all(item.content_path.join("src")).unwrap();
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        for name in ["a.txt", "b.txt", "src/c.txt", "src/d.txt"] {
+            fs::write(item.content_path.join(name), name).unwrap();
+        }
+        // Too long for the backup name of the replaced file, so replacing it
+        // fails
+        let long_name = "x".repeat(250);
+        fs::write(item.content_path.join(&long_name), "new").unwrap();
+        fs::write(root.join("project").join(&long_name), "old").unwrap();
+
+        let error = restore_item_merging(&item, &root.join("project"), &mut |_| {})
+            .unwrap_err()
+            .to_string();
+
+        // Whatever got moved before the failure is named
+        let moved = ["a.txt", "b.txt", "src/c.txt", "src/d.txt"]
+            .into_iter()
+            .filter(|name| root.join("project").join(name).exists())
+            .collect::<Vec<_>>();
+        for name in &moved {
+            assert!(!item.content_path.join(name).exists());
+        }
+        assert_eq!(error.contains("already merged into"), !moved.is_empty());
+        for name in moved.iter().take(3) {
+            assert!(error.contains(name), "{}", error);
+        }
+        assert_eq!(
+            fs::read_to_string(root.join("project").join(&long_name)).unwrap(),
+            "old"
+        );
+        assert!(item.info_path.exists());
+
+        let merged = ["a.txt", "b.txt", "c.txt", "d.txt"].map(|name| root.join(name));
+        assert_eq!(
+            merge_error(Error::other("failed"), &root, &merged).to_string(),
+            format!(
+                "failed, already merged into {}: a.txt, b.txt, c.txt and 1 more",
+                root.display()
             )
         );
     }
@@ -170,7 +1653,7 @@ mod tests {
             env::remove_var("HOME");
         }
 
-        let result = std::panic::catch_unwind(|| get_trash_dirs());
+        let result = std::panic::catch_unwind(get_trash_dirs);
         assert!(result.is_err());
 
         unsafe {
@@ -182,34 +1665,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn can_purge_expired_stages() {
+        let root = TempDir::new("purge");
+        let trash_dir = trash_dir(&root);
+        let now = Local::now();
+        let old = create_stage(&trash_dir, now - TimeDelta::days(2)).unwrap();
+        let recent = create_stage(&trash_dir, now - TimeDelta::hours(1)).unwrap();
+        fs::create_dir(trash_dir.staging.join("notes")).unwrap();
+
+        assert!(purge_stages(&trash_dir, Some(TimeDelta::days(1)), now).is_empty());
+        assert!(!old.root.exists());
+        assert!(recent.root.exists());
+        // Unknown directories are left alone unless everything is purged
+        assert!(trash_dir.staging.join("notes").exists());
+
+        assert!(purge_stages(&trash_dir, None, now).is_empty());
+        assert!(list_files_from_dir(&trash_dir.staging).unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_skip_files_without_trashinfo_extension() {
+        let root = TempDir::new("list");
+        let trash_dir = trash_dir(&root);
+        let entry = trash_file(&trash_dir, "a.txt", &root.join("a.txt"));
+        // E.g. a backup left by an editor, it's not an entry
+        fs::copy(&entry.info_path, trash_dir.info.join("a.txt.trashinfo~")).unwrap();
+
+        let entries = list_entries_of(&trash_dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].info_path, entry.info_path);
+    }
+
+    #[test]
+    fn can_undo_emptying() {
+        let root = TempDir::new("empty");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let entries = [
+            trash_file(&trash_dir, "a.txt", &root.join("a.txt")),
+            trash_file(&trash_dir, "b.txt", &root.join("b.txt")),
+        ];
+        fs::write(&trash_dir.directory_sizes, "").unwrap();
+
+        let staged = empty_trash_dir(&trash_dir, Local::now()).unwrap();
+        assert_eq!(staged.len(), 2);
+        assert!(list_files_from_dir(&trash_dir.files).unwrap().is_empty());
+        assert!(list_files_from_dir(&trash_dir.info).unwrap().is_empty());
+        assert!(!trash_dir.directory_sizes.exists());
+
+        for (entry, stage) in &staged {
+            unstage_item(stage, entry).unwrap();
+        }
+        for entry in &entries {
+            assert!(entry.content_path.exists());
+            assert!(entry.info_path.exists());
+        }
+        // The stage is gone with its last item
+        assert!(list_files_from_dir(&trash_dir.staging).unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_empty_other_trashes_when_one_fails() {
+        let root = TempDir::new("empty-partly");
+        let broken = trash_dir(&root.join("broken"));
+        fs::remove_dir(&broken.files).unwrap();
+        let trash_dir = trash_dir(&root.join("trash"));
+        trash_file(&trash_dir, "a.txt", &root.join("a.txt"));
+
+        let (staged, error) = empty_trash_dirs(&[broken.clone(), trash_dir.clone()], Local::now());
+
+        // What was staged is still reported, so it can be undone
+        assert_eq!(staged.len(), 1);
+        assert_eq!(staged[0].0.display_name, "a.txt");
+        assert!(staged[0].1.join("files/a.txt").exists());
+        assert!(
+            error
+                .unwrap()
+                .to_string()
+                .contains(&*broken.root.to_string_lossy())
+        );
+    }
+
     #[test]
     fn can_restore_item() {
-        let (_, files_dir, info_dir) = get_trash_dirs();
-        let test_file = files_dir.join("test_restore.txt");
-        let restore_location = PathBuf::from("/tmp").join("test_restore.txt");
-        let info_location = info_dir.join("test_restore.txt.trashinfo");
+        let root = TempDir::new("restore-item");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let entry = trash_entry(
+            &trash_dir,
+            "test_restore.txt",
+            &root.join("test_restore.txt"),
+        );
 
-        fs::write(&test_file, "Test content").unwrap();
+        fs::write(&entry.content_path, "Test content").unwrap();
         fs::write(
-            &info_location,
+            &entry.info_path,
             format!(
                 "[Trash Info]\nPath={}\nDeletionDate=2023-10-01T12:00:00",
-                restore_location.display(),
+                entry.restore_location.display(),
             ),
         )
         .unwrap();
 
-        let entry = TrashEntry {
-            display_name: "test_restore.txt".to_string(),
-            restore_location: restore_location.clone(),
-            info_path: info_location.clone(),
-            content_path: test_file.clone(),
-            date: Local::now(),
-        };
-
-        restore_item(&entry).unwrap();
-        assert!(restore_location.exists());
-
-        fs::remove_file(restore_location.clone()).unwrap(); // Clean up after test
+        restore_item_to(&entry, &entry.restore_location, &mut |_| {}).unwrap();
+        assert!(entry.restore_location.exists());
+        assert!(!entry.info_path.exists());
     }
 }
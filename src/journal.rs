@@ -0,0 +1,246 @@
+use std::{cmp::Reverse, io, path::PathBuf};
+
+use chrono::{DateTime, Local};
+use ratatui::widgets::TableState;
+
+use crate::{
+    io::{list_staged, purge_expired, retrash_item, unstage_item},
+    trash_entry::TrashEntry,
+};
+
+/// A change to the trash that can be reversed
+pub enum Operation {
+    /// The item was restored to `target`
+    Restored { entry: TrashEntry, target: PathBuf },
+    /// The item was deleted, it waits in `stage` until it's purged after the
+    /// grace period
+    Deleted { entry: TrashEntry, stage: PathBuf },
+}
+
+impl Operation {
+    pub fn entry(&self) -> &TrashEntry {
+        match self {
+            Operation::Restored { entry, .. } | Operation::Deleted { entry, .. } => entry,
+        }
+    }
+
+    fn undo(&self) -> Result<(), io::Error> {
+        match self {
+            Operation::Restored { entry, target } => retrash_item(entry, target),
+            Operation::Deleted { entry, stage } => unstage_item(stage, entry),
+        }
+    }
+}
+
+/// Operations done in one go, e.g. a bulk restore. They are undone together.
+pub struct Record {
+    pub operations: Vec<Operation>,
+    pub time: DateTime<Local>,
+}
+
+impl Record {
+    pub fn description(&self) -> String {
+        let action = match self.operations.first() {
+            Some(Operation::Restored { .. }) => "Restored",
+            _ => "Deleted",
+        };
+
+        match self.operations.as_slice() {
+            [operation] => format!(
+                "{} {}",
+                action,
+                operation.entry().display_name.to_string_lossy()
+            ),
+            operations => format!("{} {} items", action, operations.len()),
+        }
+    }
+
+    /// Past tense of the undo, for messages
+    pub fn undo_action(&self) -> &'static str {
+        match self.operations.first() {
+            Some(Operation::Restored { .. }) => "put back in the trash",
+            _ => "undeleted",
+        }
+    }
+}
+
+/// What was done in this session and the deletes still in their grace
+/// period, newest first.
+pub struct Journal {
+    pub records: Vec<Record>,
+    pub state: TableState,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal {
+            records: Vec::new(),
+            state: TableState::default(),
+        }
+    }
+
+    /// A journal with the deletes that can still be undone, including those
+    /// of earlier sessions and of the command line.
+    pub fn load() -> Self {
+        let mut journal = Journal::new();
+
+        for (entry, stage, deleted_at) in list_staged() {
+            let operation = Operation::Deleted { entry, stage };
+
+            match journal
+                .records
+                .iter_mut()
+                .find(|record| record.time == deleted_at)
+            {
+                Some(record) => record.operations.push(operation),
+                None => journal.records.push(Record {
+                    operations: vec![operation],
+                    time: deleted_at,
+                }),
+            }
+        }
+
+        journal.records.sort_by_key(|record| Reverse(record.time));
+        if !journal.records.is_empty() {
+            journal.state.select_first();
+        }
+
+        journal
+    }
+
+    pub fn push(&mut self, operations: Vec<Operation>) {
+        if operations.is_empty() {
+            return;
+        }
+
+        self.records.insert(
+            0,
+            Record {
+                operations,
+                time: Local::now(),
+            },
+        );
+        self.state.select_first();
+    }
+
+    /// Undoes the record at `index`. Operations that fail stay in the record,
+    /// so they can be tried again.
+    pub fn undo(&mut self, index: usize) -> Option<Vec<(TrashEntry, io::Result<()>)>> {
+        let record = self.records.get_mut(index)?;
+        let mut results = Vec::new();
+
+        record.operations.retain(|operation| {
+            let result = operation.undo();
+            let is_undone = result.is_ok();
+            results.push((operation.entry().clone(), result));
+            !is_undone
+        });
+
+        if record.operations.is_empty() {
+            self.records.remove(index);
+        }
+
+        if self.records.is_empty() {
+            self.state.select(None);
+        } else if self
+            .state
+            .selected()
+            .is_some_and(|i| i >= self.records.len())
+        {
+            self.state.select_last();
+        }
+
+        Some(results)
+    }
+
+    /// Permanently deletes every staged item without waiting for the grace
+    /// period, deletes can't be undone anymore.
+    pub fn purge(&mut self) -> Result<(), io::Error> {
+        purge_expired(None)?;
+
+        self.records
+            .retain(|record| !matches!(record.operations.first(), Some(Operation::Deleted { .. })));
+        if self.records.is_empty() {
+            self.state.select(None);
+        } else {
+            self.state.select_first();
+        }
+
+        Ok(())
+    }
+
+    pub fn next(&mut self) {
+        if !self.records.is_empty() {
+            self.state.select(Some(
+                self.state
+                    .selected()
+                    .map_or(0, |i| (i + 1) % self.records.len()),
+            ));
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if !self.records.is_empty() {
+            self.state.select(Some(match self.state.selected() {
+                Some(0) | None => self.records.len() - 1,
+                Some(i) => i - 1,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        io::{list_files_from_dir, restore_item_to, stage_item},
+        test_utils::{TempDir, trash_dir, trash_entry},
+        trash_info::TrashInfo,
+    };
+
+    #[test]
+    fn can_undo_restore_and_delete() {
+        let root = TempDir::new("journal");
+        let trash_dir = trash_dir(&root.join("trash"));
+
+        let date = Local::now() - chrono::TimeDelta::days(3);
+        let entry = TrashEntry {
+            date,
+            ..trash_entry(&trash_dir, "notes.txt", &root.join("notes.txt"))
+        };
+        fs::write(&entry.content_path, "notes").unwrap();
+        fs::write(&entry.info_path, "").unwrap();
+
+        let mut journal = Journal::new();
+
+        let stage = stage_item(&entry, Local::now()).unwrap();
+        assert!(!entry.info_path.exists());
+        journal.push(vec![Operation::Deleted {
+            entry: entry.clone(),
+            stage,
+        }]);
+        journal.undo(0).unwrap();
+        assert!(entry.content_path.exists());
+        assert!(entry.info_path.exists());
+
+        restore_item_to(&entry, &entry.restore_location, &mut |_| {}).unwrap();
+        journal.push(vec![Operation::Restored {
+            entry: entry.clone(),
+            target: entry.restore_location.clone(),
+        }]);
+        journal.undo(0).unwrap();
+
+        assert!(!entry.restore_location.exists());
+        assert!(journal.records.is_empty());
+        assert!(list_files_from_dir(&trash_dir.staging).unwrap().is_empty());
+        assert_eq!(
+            TrashInfo::read(&entry.info_path)
+                .unwrap()
+                .deletion_date
+                .timestamp(),
+            date.timestamp()
+        );
+    }
+}
@@ -1,14 +1,50 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::mpsc::TryRecvError,
+};
+
 use crate::{
-    io::{get_trash_dirs, list_files_from_dir},
+    io::list_trash_entries,
+    query::{ItemState, Query},
+    sizes::{SizeReceiver, load_sizes},
     trash_entry::TrashEntry,
-    utils::SortMode,
+    utils::{SortMode, search_items},
 };
 use ratatui::widgets::TableState;
 
+pub enum ListRow {
+    /// Index into `items` of an item matching the filter, with the matched
+    /// ranges of its name
+    Item(usize, Option<Vec<Range<usize>>>),
+    /// An original directory and the indexes of the matching items that
+    /// came from it, they are listed below it unless it's folded
+    Group(PathBuf, Vec<usize>),
+}
+
 pub struct ListContainer {
     pub items: Vec<TrashEntry>,
     list_size: usize,
     pub state: TableState,
+    /// Info paths of the marked items, they stay valid across sorting and
+    /// refreshing
+    pub marked: HashSet<PathBuf>,
+    /// The rows on screen, the table state indexes into this
+    pub visible: Vec<ListRow>,
+    filter: String,
+    query: Query,
+    /// Why the filter can't be parsed, the last valid one stays in effect
+    pub filter_error: Option<String>,
+    /// Items are grouped by the directory they came from
+    pub is_grouped: bool,
+    /// Groups that are unfolded
+    expanded: HashSet<PathBuf>,
+    /// Sizes by content path, directories are added as they're measured
+    pub sizes: HashMap<PathBuf, u64>,
+    size_receiver: Option<SizeReceiver>,
+    /// By content path, gathered once a filter needs them
+    item_states: HashMap<PathBuf, ItemState>,
 }
 
 impl ListContainer {
@@ -17,6 +53,16 @@ impl ListContainer {
             state: TableState::default(),
             items: Vec::new(),
             list_size: size,
+            marked: HashSet::new(),
+            visible: Vec::new(),
+            filter: String::new(),
+            query: Query::default(),
+            filter_error: None,
+            is_grouped: false,
+            expanded: HashSet::new(),
+            sizes: HashMap::new(),
+            size_receiver: None,
+            item_states: HashMap::new(),
         };
 
         list.refresh(sort_mode);
@@ -25,47 +71,217 @@ impl ListContainer {
     }
 
     pub fn refresh(&mut self, sort_mode: &SortMode) {
-        let (_, _, info_dir) = get_trash_dirs();
-        let files = list_files_from_dir(&info_dir);
+        let selected = self.selected_key();
 
-        if files.is_none() {
-            panic!("Error listing files from {}", info_dir.display());
+        self.items = list_trash_entries();
+        self.marked
+            .retain(|info_path| self.items.iter().any(|item| item.info_path == *info_path));
+        (self.sizes, self.size_receiver) = load_sizes(&self.items);
+        self.item_states.clear();
+
+        self.sort_items(sort_mode);
+        self.update_visible(selected);
+    }
+
+    /// Whether directories are still being measured
+    pub fn is_measuring(&self) -> bool {
+        self.size_receiver.is_some()
+    }
+
+    /// Takes the sizes measured so far, the list is sorted again when it's
+    /// sorted by size.
+    pub fn receive_sizes(&mut self, sort_mode: &SortMode) {
+        let Some(receiver) = &self.size_receiver else {
+            return;
+        };
+
+        let mut is_done = false;
+        let mut has_new_sizes = false;
+        loop {
+            match receiver.try_recv() {
+                Ok((content_path, size)) => {
+                    self.sizes.insert(content_path, size);
+                    has_new_sizes = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    is_done = true;
+                    break;
+                }
+            }
         }
 
-        self.items = files
-            .unwrap()
-            .iter()
-            .filter_map(|file| TrashEntry::from_trash_info(file).ok())
-            .collect::<Vec<TrashEntry>>();
+        if is_done {
+            self.size_receiver = None;
+        }
+        let is_sized =
+            matches!(sort_mode, SortMode::SizeAsc | SortMode::SizeDesc) || self.query.uses_sizes();
+        if has_new_sizes && is_sized {
+            self.sort(sort_mode);
+        }
+    }
 
-        self.sort(sort_mode);
+    pub fn set_filter(&mut self, filter: &str) {
+        if self.filter == filter {
+            return;
+        }
 
-        if self.state.selected().is_none() {
-            self.state.select_first();
+        self.filter = filter.to_string();
+        match Query::parse(filter) {
+            Ok(query) => {
+                self.query = query;
+                self.filter_error = None;
+            }
+            Err(e) => {
+                self.filter_error = Some(e);
+                return;
+            }
         }
+
+        self.update_visible(None);
+        // The best match comes first
+        self.state.select((!self.visible.is_empty()).then_some(0));
+    }
+
+    // Keeps the selection on the same row when it's still visible, see
+    // `selected_key`
+    fn update_visible(&mut self, selected: Option<PathBuf>) {
+        if self.query.uses_item_states() {
+            for item in &self.items {
+                self.item_states
+                    .entry(item.content_path.clone())
+                    .or_insert_with(|| ItemState::of(item));
+            }
+        }
+
+        let results = search_items(&self.items, &self.query, &self.sizes, &self.item_states);
+
+        self.visible = match self.is_grouped {
+            true => self.group(results),
+            false => results
+                .into_iter()
+                .map(|(index, ranges)| ListRow::Item(index, ranges))
+                .collect(),
+        };
+
+        let position = selected.and_then(|key| {
+            self.visible
+                .iter()
+                .position(|row| self.row_key(row) == key.as_path())
+        });
+
+        match (position, self.state.selected()) {
+            _ if self.visible.is_empty() => self.state.select(None),
+            (Some(position), _) => self.state.select(Some(position)),
+            (None, Some(selected)) => self
+                .state
+                .select(Some(selected.min(self.visible.len() - 1))),
+            (None, None) => self.state.select_first(),
+        }
+    }
+
+    // Groups come in the order of their first item, so the best match or
+    // the first item in sort order decides
+    fn group(&self, results: Vec<(usize, Option<Vec<Range<usize>>>)>) -> Vec<ListRow> {
+        let mut groups: Vec<(&Path, Vec<usize>, Vec<ListRow>)> = Vec::new();
+        let mut positions = HashMap::new();
+
+        for (index, ranges) in results {
+            let dir = original_dir(&self.items[index]);
+            let position = *positions.entry(dir).or_insert_with(|| {
+                groups.push((dir, Vec::new(), Vec::new()));
+                groups.len() - 1
+            });
+
+            let (_, indexes, rows) = &mut groups[position];
+            indexes.push(index);
+            if self.is_expanded(dir) {
+                rows.push(ListRow::Item(index, ranges));
+            }
+        }
+
+        groups
+            .into_iter()
+            .flat_map(|(dir, indexes, rows)| {
+                std::iter::once(ListRow::Group(dir.to_path_buf(), indexes)).chain(rows)
+            })
+            .collect()
+    }
+
+    /// Whether the items of the group are shown, they always are while
+    /// filtering
+    pub fn is_expanded(&self, dir: &Path) -> bool {
+        !self.filter.is_empty() || self.expanded.contains(dir)
+    }
+
+    pub fn toggle_grouping(&mut self) {
+        let selected = self.selected_key();
+
+        self.is_grouped = !self.is_grouped;
+        self.update_visible(selected);
+    }
+
+    /// Folds or unfolds the selected group, or the group of the selected item
+    pub fn toggle_fold(&mut self) {
+        let dir = match self.state.selected().and_then(|row| self.visible.get(row)) {
+            Some(ListRow::Group(dir, _)) => dir.clone(),
+            Some(ListRow::Item(index, _)) if self.is_grouped => {
+                original_dir(&self.items[*index]).to_path_buf()
+            }
+            _ => return,
+        };
+
+        if !self.expanded.remove(&dir) {
+            self.expanded.insert(dir.clone());
+        }
+        self.update_visible(Some(dir));
+    }
+
+    // What identifies a row across updates: the info path of an item, the
+    // directory of a group
+    fn row_key<'a>(&'a self, row: &'a ListRow) -> &'a Path {
+        match row {
+            ListRow::Item(index, _) => &self.items[*index].info_path,
+            ListRow::Group(dir, _) => dir,
+        }
+    }
+
+    fn selected_key(&self) -> Option<PathBuf> {
+        self.state
+            .selected()
+            .and_then(|row| self.visible.get(row))
+            .map(|row| self.row_key(row).to_path_buf())
     }
 
     pub fn next(&mut self) {
-        if self.items.is_empty() {
+        if self.visible.is_empty() {
             return;
         }
 
         match self.state.selected() {
-            Some(val) if val == self.items.len() - 1 => self.state.select_first(),
+            Some(val) if val >= self.visible.len() - 1 => self.state.select_first(),
             _ => self.state.select_next(),
         }
     }
 
     pub fn prev(&mut self) {
+        if self.visible.is_empty() {
+            return;
+        }
+
         match self.state.selected() {
-            Some(0) => self.state.select_last(),
+            Some(0) => self.state.select(Some(self.visible.len() - 1)),
             _ => self.state.select_previous(),
         }
     }
 
     pub fn scroll_next(&mut self) {
+        let last = self.visible.len().saturating_sub(1);
+
         match self.state.selected() {
-            Some(val) => self.state.select(Some(val.saturating_add(self.list_size))),
+            Some(val) => self
+                .state
+                .select(Some(val.saturating_add(self.list_size).min(last))),
             _ => self.state.select_first(),
         }
     }
@@ -77,13 +293,109 @@ impl ListContainer {
         }
     }
 
+    /// The highlighted item, which is not necessarily `items[selected]` while
+    /// filtering. `None` when a group is highlighted.
     pub fn get_slected_item(&self) -> Option<&TrashEntry> {
-        self.state
-            .selected()
-            .and_then(|index| self.items.get(index))
+        match self.state.selected().and_then(|row| self.visible.get(row)) {
+            Some(ListRow::Item(index, _)) => self.items.get(*index),
+            _ => None,
+        }
+    }
+
+    // The highlighted item, or every item of the highlighted group
+    fn get_selected_items(&self) -> Vec<&TrashEntry> {
+        match self.state.selected().and_then(|row| self.visible.get(row)) {
+            Some(ListRow::Item(index, _)) => vec![&self.items[*index]],
+            Some(ListRow::Group(_, indexes)) => {
+                indexes.iter().map(|index| &self.items[*index]).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Marks the highlighted item, or the whole group. A group that's
+    /// completely marked is unmarked.
+    pub fn toggle_mark(&mut self) {
+        let info_paths = self
+            .get_selected_items()
+            .into_iter()
+            .map(|item| item.info_path.clone())
+            .collect::<Vec<PathBuf>>();
+
+        if info_paths
+            .iter()
+            .all(|info_path| self.marked.contains(info_path))
+        {
+            for info_path in &info_paths {
+                self.marked.remove(info_path);
+            }
+        } else {
+            self.marked.extend(info_paths);
+        }
+    }
+
+    /// Marks every item, or clears the marks when everything is marked already.
+    pub fn toggle_mark_all(&mut self) {
+        if self.marked.len() == self.items.len() {
+            self.marked.clear();
+        } else {
+            self.marked = self
+                .items
+                .iter()
+                .map(|item| item.info_path.clone())
+                .collect();
+        }
+    }
+
+    pub fn invert_marks(&mut self) {
+        self.marked = self
+            .items
+            .iter()
+            .map(|item| item.info_path.clone())
+            .filter(|info_path| !self.marked.contains(info_path))
+            .collect();
+    }
+
+    /// Marks every item matched by the current filter, folded ones too.
+    pub fn mark_visible(&mut self) {
+        let indexes = self
+            .visible
+            .iter()
+            .flat_map(|row| match row {
+                ListRow::Item(index, _) => vec![*index],
+                ListRow::Group(_, indexes) => indexes.clone(),
+            })
+            .collect::<Vec<usize>>();
+
+        self.marked.extend(
+            indexes
+                .into_iter()
+                .map(|index| self.items[index].info_path.clone()),
+        );
+    }
+
+    /// The marked items, or the selected one when nothing is marked. A
+    /// selected group stands for all its items.
+    pub fn get_target_items(&self) -> Vec<TrashEntry> {
+        if self.marked.is_empty() {
+            return self.get_selected_items().into_iter().cloned().collect();
+        }
+
+        self.items
+            .iter()
+            .filter(|item| self.marked.contains(&item.info_path))
+            .cloned()
+            .collect()
     }
 
     pub fn sort(&mut self, sort_mode: &SortMode) {
+        let selected = self.selected_key();
+
+        self.sort_items(sort_mode);
+        self.update_visible(selected);
+    }
+
+    fn sort_items(&mut self, sort_mode: &SortMode) {
         match sort_mode {
             SortMode::NameAsc => self
                 .items
@@ -91,8 +403,17 @@ impl ListContainer {
             SortMode::NameDesc => self
                 .items
                 .sort_by(|a, b| a.display_name.cmp(&b.display_name)),
-            SortMode::DateAsc => self.items.sort_by(|a, b| b.date.cmp(&a.date)),
-            SortMode::DateDesc => self.items.sort_by(|a, b| a.date.cmp(&b.date)),
+            SortMode::DateAsc => self.items.sort_by_key(|item| std::cmp::Reverse(item.date)),
+            SortMode::DateDesc => self.items.sort_by_key(|item| item.date),
+            // Items still being measured come last
+            SortMode::SizeDesc => self
+                .items
+                .sort_by_key(|item| std::cmp::Reverse(self.sizes.get(&item.content_path))),
+            SortMode::SizeAsc => self.items.sort_by_key(|item| {
+                self.sizes
+                    .get(&item.content_path)
+                    .map_or((true, 0), |size| (false, *size))
+            }),
         }
     }
 
@@ -100,3 +421,153 @@ impl ListContainer {
         self.list_size = size;
     }
 }
+
+/// The directory an item was trashed from
+pub fn original_dir(item: &TrashEntry) -> &Path {
+    item.restore_location.parent().unwrap_or(Path::new("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::trash_entry, trash_dir::TrashDir};
+
+    // Nothing is read from the trash, it doesn't have to exist
+    fn entry(path: &str) -> TrashEntry {
+        let trash_dir = TrashDir::new(Path::new("/nonexistent/trash"), None);
+
+        trash_entry(&trash_dir, &path.replace('/', "_"), Path::new(path))
+    }
+
+    fn list_of(items: Vec<TrashEntry>) -> ListContainer {
+        let mut list = ListContainer {
+            items,
+            list_size: 10,
+            state: TableState::default(),
+            marked: HashSet::new(),
+            visible: Vec::new(),
+            filter: String::new(),
+            query: Query::default(),
+            filter_error: None,
+            is_grouped: false,
+            expanded: HashSet::new(),
+            sizes: HashMap::new(),
+            size_receiver: None,
+            item_states: HashMap::new(),
+        };
+        list.update_visible(None);
+        list
+    }
+
+    #[test]
+    fn can_select_filtered_item() {
+        let mut list = list_of(vec![
+            entry("/tmp/alpha.txt"),
+            entry("/tmp/beta.txt"),
+            entry("/tmp/gamma.txt"),
+        ]);
+
+        list.set_filter("gamma");
+        assert_eq!(list.get_slected_item().unwrap().display_name, "gamma.txt");
+
+        list.sort(&SortMode::NameAsc);
+        assert_eq!(list.get_slected_item().unwrap().display_name, "gamma.txt");
+
+        list.set_filter("");
+        list.next();
+        assert_eq!(list.get_slected_item().unwrap().display_name, "beta.txt");
+    }
+
+    #[test]
+    fn can_group_by_directory() {
+        let mut list = list_of(vec![
+            entry("/home/config.toml"),
+            entry("/srv/config.toml"),
+            entry("/home/notes.txt"),
+        ]);
+        list.toggle_grouping();
+
+        assert_eq!(list.visible.len(), 2);
+        assert!(
+            matches!(&list.visible[0], ListRow::Group(dir, items) if dir == Path::new("/home") && items.len() == 2)
+        );
+        assert_eq!(list.get_target_items().len(), 2);
+
+        list.toggle_fold();
+        assert_eq!(list.visible.len(), 4);
+        list.next();
+        assert_eq!(
+            list.get_slected_item().unwrap().restore_location,
+            Path::new("/home/config.toml")
+        );
+
+        // Folding from an item selects its group again
+        list.toggle_fold();
+        assert_eq!(list.visible.len(), 2);
+        assert!(list.get_slected_item().is_none());
+    }
+
+    #[test]
+    fn can_mark_filtered_items() {
+        let mut list = list_of(vec![
+            entry("/tmp/alpha.txt"),
+            entry("/tmp/beta.txt"),
+            entry("/srv/gamma.txt"),
+        ]);
+        let marked_names = |list: &ListContainer| {
+            let mut names = list
+                .get_target_items()
+                .into_iter()
+                .map(|item| item.display_name.to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        // Marks stay when the filter changes
+        list.set_filter("alpha");
+        list.toggle_mark();
+        list.set_filter("beta");
+        list.mark_visible();
+        list.set_filter("");
+        assert_eq!(marked_names(&list), ["alpha.txt", "beta.txt"]);
+        list.sort(&SortMode::NameDesc);
+        assert_eq!(marked_names(&list), ["alpha.txt", "beta.txt"]);
+
+        // Toggling a marked item unmarks it
+        list.set_filter("beta");
+        list.toggle_mark();
+        assert_eq!(marked_names(&list), ["alpha.txt"]);
+
+        // Inverting and marking all cover the hidden items too
+        list.invert_marks();
+        assert_eq!(marked_names(&list), ["beta.txt", "gamma.txt"]);
+        list.toggle_mark_all();
+        assert_eq!(list.marked.len(), 3);
+        list.toggle_mark_all();
+        assert!(list.marked.is_empty());
+
+        // Groups unfold while filtering, only the matching items are marked
+        list.set_filter("path:/tmp/");
+        list.toggle_grouping();
+        assert_eq!(list.visible.len(), 3);
+        list.mark_visible();
+        assert_eq!(marked_names(&list), ["alpha.txt", "beta.txt"]);
+
+        // A folded group is marked and unmarked as a whole
+        list.set_filter("");
+        assert_eq!(list.visible.len(), 2);
+        let tmp = list
+            .visible
+            .iter()
+            .position(|row| matches!(row, ListRow::Group(dir, _) if dir == Path::new("/tmp")));
+        list.state.select(tmp);
+        list.toggle_mark();
+        assert!(list.marked.is_empty());
+        list.toggle_mark();
+        assert_eq!(marked_names(&list), ["alpha.txt", "beta.txt"]);
+        list.marked.clear();
+        list.mark_visible();
+        assert_eq!(list.marked.len(), 3);
+    }
+}
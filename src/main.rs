@@ -1,18 +1,42 @@
 mod app;
+mod browser;
+mod cli;
+mod diagnostics;
+mod diff;
 mod events;
 mod io;
+mod journal;
 mod list;
+mod preview;
+mod query;
+mod restore;
+mod sizes;
+#[cfg(test)]
+mod test_utils;
+mod trash_dir;
 mod trash_entry;
+mod trash_info;
 mod ui;
 mod utils;
 
-use crate::app::App;
+use std::{env, ffi::OsString, process::ExitCode};
+
+use crate::{app::App, utils::grace_period};
 use color_eyre::eyre::Result;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     color_eyre::install()?;
+
+    let args = env::args_os().skip(1).collect::<Vec<OsString>>();
+    if !args.is_empty() {
+        return Ok(cli::run(&args));
+    }
+
+    // Not worth failing over, whatever is left is purged next time
+    let _ = io::purge_expired(Some(grace_period()));
+
     let mut terminal = ratatui::init();
     let result = App::new(&mut terminal).run(&mut terminal);
     ratatui::restore();
-    result
+    result.map(|_| ExitCode::SUCCESS)
 }
@@ -0,0 +1,158 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{Error, Read},
+    path::{Path, PathBuf},
+};
+
+/// Only the start of a file is read, so big files preview as fast as small ones
+const PREVIEW_BYTES: u64 = 16 * 1024;
+const MAX_LINES: usize = 200;
+const MAX_CHILDREN: usize = 200;
+const HEXDUMP_WIDTH: usize = 16;
+
+pub enum Preview {
+    Text(Vec<String>),
+    /// Hexdump lines of the first bytes
+    Binary(Vec<String>),
+    /// Names of the children, directories first, and whether some were left
+    /// out
+    Directory(Vec<(OsString, bool)>, bool),
+    Symlink(PathBuf),
+    Error(String),
+}
+
+impl Preview {
+    /// Reads what's needed to preview `path`, without following symlinks.
+    pub fn read(path: &Path) -> Self {
+        let result = fs::symlink_metadata(path).and_then(|meta| {
+            if meta.is_symlink() {
+                fs::read_link(path).map(Preview::Symlink)
+            } else if meta.is_dir() {
+                read_children(path)
+            } else if meta.is_file() {
+                read_head(path)
+            } else {
+                Ok(Preview::Error("Not a regular file".to_string()))
+            }
+        });
+
+        result.unwrap_or_else(|e| Preview::Error(e.to_string()))
+    }
+}
+
+fn read_head(path: &Path) -> Result<Preview, Error> {
+    let mut head = Vec::new();
+    File::open(path)?
+        .take(PREVIEW_BYTES)
+        .read_to_end(&mut head)?;
+
+    // The cap may cut a multi-byte character in half, that's still text
+    let text = match std::str::from_utf8(&head) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+
+    Ok(match text {
+        Some(text) if !text.contains('\0') => Preview::Text(
+            text.lines()
+                .take(MAX_LINES)
+                .map(|line| line.replace('\t', "    "))
+                .collect(),
+        ),
+        _ => Preview::Binary(hexdump(&head[..head.len().min(MAX_LINES * HEXDUMP_WIDTH)])),
+    })
+}
+
+// Huge directories aren't read to the end, only the children read are
+// sorted then
+fn read_children(path: &Path) -> Result<Preview, Error> {
+    let mut entries = fs::read_dir(path)?.filter_map(|entry| entry.ok());
+    let mut children = entries
+        .by_ref()
+        .take(MAX_CHILDREN)
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            (entry.file_name(), is_dir)
+        })
+        .collect::<Vec<_>>();
+    children.sort_by(|(a, a_is_dir), (b, b_is_dir)| b_is_dir.cmp(a_is_dir).then(a.cmp(b)));
+
+    Ok(Preview::Directory(children, entries.next().is_some()))
+}
+
+/// `00000010  6c 6f 0a 00 ...  |lo..|`
+fn hexdump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEXDUMP_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+
+            format!(
+                "{:08x}  {:<width$}  |{}|",
+                i * HEXDUMP_WIDTH,
+                hex,
+                ascii,
+                width = HEXDUMP_WIDTH * 3 - 1
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn can_preview_items() {
+        let root = TempDir::new("preview");
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        fs::write(root.join("dir/notes.txt"), "first\n\tsecond\n").unwrap();
+        fs::write(root.join("dir/data.bin"), [0x7f, b'E', b'L', b'F', 0, 1]).unwrap();
+        symlink("dir/notes.txt", root.join("link")).unwrap();
+
+        assert!(matches!(
+            Preview::read(&root.join("dir/notes.txt")),
+            Preview::Text(lines) if lines == ["first", "    second"]
+        ));
+        assert!(matches!(
+            Preview::read(&root.join("dir/data.bin")),
+            Preview::Binary(lines) if lines[0].starts_with("00000000  7f 45 4c 46 00 01")
+                && lines[0].ends_with("|.ELF..|")
+        ));
+        assert!(matches!(
+            Preview::read(&root.join("dir")),
+            Preview::Directory(children, false) if children[0] == (OsString::from("sub"), true)
+                && children[1].0 == "data.bin"
+        ));
+        assert!(matches!(
+            Preview::read(&root.join("link")),
+            Preview::Symlink(target) if target == Path::new("dir/notes.txt")
+        ));
+
+        fs::create_dir(root.join("big")).unwrap();
+        for i in 0..=MAX_CHILDREN {
+            fs::write(root.join("big").join(i.to_string()), "").unwrap();
+        }
+        assert!(matches!(
+            Preview::read(&root.join("big")),
+            Preview::Directory(children, true) if children.len() == MAX_CHILDREN
+        ));
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    fs,
+    ops::{Bound, RangeBounds},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+
+use crate::{
+    trash_entry::TrashEntry,
+    utils::{expand_home, parse_duration},
+};
+
+// Lower and upper end of what a filter accepts
+type Bounds<T> = (Bound<T>, Bound<T>);
+
+/// A search typed into the filter bar, e.g. `report ext:pdf deleted:<7d`.
+/// Every filter has to match, the remaining words are matched fuzzily
+/// against the names.
+#[derive(Default)]
+pub struct Query {
+    pub text: String,
+    filters: Vec<Filter>,
+}
+
+enum Filter {
+    /// Part of the original location
+    Path(String),
+    /// End of the name, without the dot and lowercase
+    Ext(String),
+    Size(Bounds<u64>),
+    Deleted(Bounds<DateTime<Local>>),
+    Type(ItemType),
+    /// Trash directories inside this path
+    Trash(PathBuf),
+    /// Something exists at the original location
+    Conflict,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ItemType {
+    File,
+    Dir,
+    Link,
+}
+
+/// What `type:` and `is:conflict` look at. Getting it stats the disk, so
+/// it's gathered once per item and refresh, not on every keystroke.
+#[derive(Clone, Copy)]
+pub struct ItemState {
+    /// `None` when the content is missing
+    item_type: Option<ItemType>,
+    is_conflict: bool,
+}
+
+impl ItemState {
+    pub fn of(item: &TrashEntry) -> Self {
+        ItemState {
+            item_type: fs::symlink_metadata(&item.content_path).ok().map(|meta| {
+                match meta.file_type() {
+                    file_type if file_type.is_symlink() => ItemType::Link,
+                    file_type if file_type.is_dir() => ItemType::Dir,
+                    _ => ItemType::File,
+                }
+            }),
+            is_conflict: fs::symlink_metadata(&item.restore_location).is_ok(),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(search: &str) -> Result<Self, String> {
+        let mut words = Vec::new();
+        let mut filters = Vec::new();
+
+        for word in search.split_whitespace() {
+            let Some((key, value)) = word
+                .split_once(':')
+                .filter(|(key, _)| !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase()))
+            else {
+                words.push(word);
+                continue;
+            };
+
+            if value.is_empty() {
+                return Err(format!("{}: needs a value", key));
+            }
+
+            filters.push(match key {
+                "path" => Filter::Path(expand_home(value).to_string_lossy().to_string()),
+                "ext" => Filter::Ext(value.trim_start_matches('.').to_lowercase()),
+                "size" => Filter::Size(parse_size_bounds(value)?),
+                "deleted" => Filter::Deleted(parse_date_bounds(value)?),
+                "type" => Filter::Type(match value {
+                    "file" => ItemType::File,
+                    "dir" => ItemType::Dir,
+                    "link" => ItemType::Link,
+                    _ => return Err(format!("type:{} - expected file, dir or link", value)),
+                }),
+                "trash" => Filter::Trash(expand_home(value)),
+                "is" if value == "conflict" => Filter::Conflict,
+                "is" => return Err(format!("is:{} - expected conflict", value)),
+                _ => return Err(format!("Unknown filter {}:", key)),
+            });
+        }
+
+        Ok(Query {
+            text: words.join(" "),
+            filters,
+        })
+    }
+
+    /// Whether the item passes every filter, `size` is `None` while it's
+    /// unknown, `state` when it isn't needed, see [`Query::uses_item_states`]
+    pub fn matches(&self, item: &TrashEntry, size: Option<u64>, state: Option<&ItemState>) -> bool {
+        self.filters.iter().all(|filter| match filter {
+            Filter::Path(part) => item
+                .restore_location
+                .to_string_lossy()
+                .contains(part.as_str()),
+            Filter::Ext(ext) => item
+                .display_name
+                .to_string_lossy()
+                .to_lowercase()
+                .ends_with(&format!(".{}", ext)),
+            Filter::Size(bounds) => size.is_some_and(|size| bounds.contains(&size)),
+            Filter::Deleted(bounds) => bounds.contains(&item.date),
+            Filter::Type(item_type) => {
+                state.is_some_and(|state| state.item_type == Some(*item_type))
+            }
+            Filter::Trash(path) => item.trash_dir.root.starts_with(path),
+            Filter::Conflict => state.is_some_and(|state| state.is_conflict),
+        })
+    }
+
+    /// Items of unknown size don't match until they're measured
+    pub fn uses_sizes(&self) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| matches!(filter, Filter::Size(_)))
+    }
+
+    /// Items without an [`ItemState`] don't match `type:` and `is:conflict`
+    pub fn uses_item_states(&self) -> bool {
+        self.filters
+            .iter()
+            .any(|filter| matches!(filter, Filter::Type(_) | Filter::Conflict))
+    }
+}
+
+// `>100M`, `<1.5G` or `10K..20K`, a range includes both ends
+fn parse_size_bounds(value: &str) -> Result<Bounds<u64>, String> {
+    let bounds = || {
+        Some(match value.split_once("..") {
+            Some((min, max)) => (
+                Bound::Included(parse_size(min)?),
+                Bound::Included(parse_size(max)?),
+            ),
+            None => match value.split_at(value.starts_with(['<', '>']) as usize) {
+                (">", size) => (Bound::Excluded(parse_size(size)?), Bound::Unbounded),
+                ("<", size) => (Bound::Unbounded, Bound::Excluded(parse_size(size)?)),
+                _ => return None,
+            },
+        })
+    };
+
+    bounds().ok_or_else(|| format!("size:{} - expected e.g. size:>100M", value))
+}
+
+// Bytes, or kibibytes and up with `K`, `M`, `G` or `T`. `KB` and `KiB` work too.
+fn parse_size(size: &str) -> Option<u64> {
+    let unit_index = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (amount, unit) = size.split_at(unit_index);
+    let amount = amount.parse::<f64>().ok()?;
+
+    let unit = unit.to_ascii_lowercase();
+    let exponent = match unit.trim_end_matches('b').trim_end_matches('i') {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return None,
+    };
+
+    Some((amount * 1024f64.powi(exponent)) as u64)
+}
+
+// `<7d` - less than 7 days ago, `>7d` - longer ago, `2025-06-01` - on that
+// day, `<2025-06-01` - before it, `2025-06-01..2025-06-30` - in between, both
+// days included
+fn parse_date_bounds(value: &str) -> Result<Bounds<DateTime<Local>>, String> {
+    let now = Local::now();
+    let error = || format!("deleted:{} - expected e.g. deleted:<7d", value);
+
+    if let Some((from, to)) = value.split_once("..") {
+        let (from, to) = (
+            parse_date(from).ok_or_else(error)?,
+            parse_date(to).ok_or_else(error)?,
+        );
+        return Ok((
+            Bound::Included(start_of_day(from).ok_or_else(error)?),
+            Bound::Excluded(start_of_day(to + TimeDelta::days(1)).ok_or_else(error)?),
+        ));
+    }
+
+    let (operator, point) = value.split_at(value.starts_with(['<', '>']) as usize);
+    if let Some(age) = parse_duration(point) {
+        let time = now - age;
+        return match operator {
+            "<" => Ok((Bound::Excluded(time), Bound::Unbounded)),
+            ">" => Ok((Bound::Unbounded, Bound::Excluded(time))),
+            _ => Err(error()),
+        };
+    }
+
+    let day = parse_date(point).ok_or_else(error)?;
+    let start = start_of_day(day).ok_or_else(error)?;
+    let end = start_of_day(day + TimeDelta::days(1)).ok_or_else(error)?;
+    Ok(match operator {
+        "<" => (Bound::Unbounded, Bound::Excluded(start)),
+        ">" => (Bound::Included(end), Bound::Unbounded),
+        _ => (Bound::Included(start), Bound::Excluded(end)),
+    })
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+fn start_of_day(day: NaiveDate) -> Option<DateTime<Local>> {
+    day.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TempDir, trash_dir, trash_entry};
+
+    fn matches(query: &Query, item: &TrashEntry, size: Option<u64>) -> bool {
+        query.matches(item, size, Some(&ItemState::of(item)))
+    }
+
+    #[test]
+    fn can_parse_and_match_queries() {
+        let root = TempDir::new("query");
+        let trash_dir = trash_dir(&root.join("trash"));
+        fs::create_dir_all(trash_dir.files.join("photos")).unwrap();
+        fs::write(trash_dir.files.join("Report.PDF"), "").unwrap();
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/Report.PDF"), "").unwrap();
+
+        let item = |name: &str, dir: &str, days_ago: i64| TrashEntry {
+            date: Local::now() - TimeDelta::days(days_ago),
+            ..trash_entry(&trash_dir, name, &root.join(dir).join(name))
+        };
+        let report = item("Report.PDF", "docs", 2);
+        let photos = item("photos", "media", 40);
+
+        let query = Query::parse("rep ext:pdf deleted:<7d").unwrap();
+        assert_eq!(query.text, "rep");
+        assert!(matches(&query, &report, None));
+        assert!(!matches(&query, &photos, None));
+
+        let query = Query::parse("size:>1.5M type:dir").unwrap();
+        assert!(query.uses_sizes());
+        assert!(query.uses_item_states());
+        // Unknown states don't match
+        assert!(!query.matches(&photos, Some(2 * 1024 * 1024), None));
+        assert!(matches(&query, &photos, Some(2 * 1024 * 1024)));
+        assert!(!matches(&query, &photos, Some(1024 * 1024)));
+        assert!(!matches(&query, &photos, None));
+        assert!(!matches(&query, &report, Some(2 * 1024 * 1024)));
+
+        let today = Local::now().date_naive();
+        let query = Query::parse(&format!(
+            "deleted:{}..{} is:conflict",
+            today - TimeDelta::days(3),
+            today
+        ))
+        .unwrap();
+        assert!(matches(&query, &report, None));
+        assert!(!matches(&query, &photos, None));
+
+        let trash = format!("trash:{}", trash_dir.root.display());
+        assert!(matches(&Query::parse(&trash).unwrap(), &photos, None));
+        assert!(!matches(
+            &Query::parse("trash:/nowhere").unwrap(),
+            &photos,
+            None
+        ));
+        assert!(matches(
+            &Query::parse("path:media/").unwrap(),
+            &photos,
+            None
+        ));
+
+        assert!(Query::parse("size:100M").is_err());
+        assert!(Query::parse("deleted:<7y").is_err());
+        assert!(Query::parse("colour:red").is_err());
+        assert!(Query::parse("ext:").is_err());
+        // Not a filter, just a name with a colon
+        assert_eq!(Query::parse("12:30.txt").unwrap().text, "12:30.txt");
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    io::{Progress, free_path, restore_item_merging, restore_item_replacing, restore_item_to},
+    trash_entry::TrashEntry,
+};
+
+/// Why an item can't be restored as it is
+#[derive(Clone)]
+pub enum Conflict {
+    /// Something already exists at the target
+    Exists,
+    /// The directory to restore into is gone. If it's in the trash, that
+    /// entry comes along.
+    MissingParent(Option<Box<TrashEntry>>),
+}
+
+/// What to do about a [`Conflict`]
+#[derive(Clone)]
+pub enum Resolution {
+    /// Replace the existing file or directory
+    Overwrite,
+    /// Restore next to it with a suffix, e.g. `video_2.avi`
+    KeepBoth,
+    /// Restore to another path, which may conflict again
+    Rename(PathBuf),
+    Skip,
+    /// Move the content of a trashed directory into the existing one
+    Merge,
+    /// Create the missing directories
+    CreateParents,
+    /// Restore the trashed parent directory first
+    RestoreParent,
+}
+
+impl Resolution {
+    fn applies_to(&self, conflict: &Conflict) -> bool {
+        match self {
+            Resolution::Skip | Resolution::Rename(_) => true,
+            Resolution::CreateParents => matches!(conflict, Conflict::MissingParent(_)),
+            Resolution::RestoreParent => matches!(conflict, Conflict::MissingParent(Some(_))),
+            Resolution::Overwrite | Resolution::KeepBoth | Resolution::Merge => {
+                matches!(conflict, Conflict::Exists)
+            }
+        }
+    }
+}
+
+pub struct RestoreJob {
+    pub entry: TrashEntry,
+    pub target: PathBuf,
+    /// Set when the queue stops at this item
+    pub conflict: Option<Conflict>,
+    /// Chosen for this item's conflict, it's applied on the next run
+    resolution: Option<Resolution>,
+}
+
+impl RestoreJob {
+    fn new(entry: TrashEntry) -> Self {
+        RestoreJob {
+            target: entry.restore_location.clone(),
+            entry,
+            conflict: None,
+            resolution: None,
+        }
+    }
+
+    /// Only a directory can be merged into a directory
+    pub fn can_merge(&self) -> bool {
+        let is_dir = |path| fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir());
+
+        is_dir(&self.entry.content_path) && is_dir(&self.target)
+    }
+}
+
+/// Restores items one by one, stopping at every conflict until it's resolved.
+pub struct RestoreQueue {
+    jobs: VecDeque<RestoreJob>,
+    /// Everything in the trash, to find the parents of restored items
+    trashed: Vec<TrashEntry>,
+    /// Info paths of the parents already restored, or attempted to
+    restored_parents: HashSet<PathBuf>,
+    /// Resolutions chosen with "apply to all", one per kind of conflict
+    resolutions_for_all: Vec<Resolution>,
+    pub apply_to_all: bool,
+    pub results: Vec<(TrashEntry, Result<(), io::Error>)>,
+    /// Where the items that can be undone ended up
+    pub restored: Vec<(TrashEntry, PathBuf)>,
+    pub skipped: usize,
+}
+
+impl RestoreQueue {
+    pub fn new(mut entries: Vec<TrashEntry>, trashed: Vec<TrashEntry>) -> Self {
+        // Parents before their children, when both are restored
+        entries.sort_by_key(|entry| entry.restore_location.components().count());
+
+        RestoreQueue {
+            jobs: entries.into_iter().map(RestoreJob::new).collect(),
+            trashed,
+            restored_parents: HashSet::new(),
+            resolutions_for_all: Vec::new(),
+            apply_to_all: false,
+            results: Vec::new(),
+            restored: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Restores the items into `dir` instead of their original locations.
+    pub fn with_target_dir(mut self, dir: &Path) -> Self {
+        for job in &mut self.jobs {
+            job.target = dir.join(&job.entry.display_name);
+        }
+
+        self
+    }
+
+    /// Restores items until one conflicts, which is then returned. `None`
+    /// means the queue is done. Items copied between filesystems report
+    /// their progress.
+    pub fn run(&mut self, progress: &mut dyn FnMut(&TrashEntry, Progress)) -> Option<&RestoreJob> {
+        while let Some(mut job) = self.jobs.pop_front() {
+            let Some(conflict) = self.find_conflict(&job.target) else {
+                let result = restore_item_to(&job.entry, &job.target, &mut |bytes| {
+                    progress(&job.entry, bytes)
+                });
+                self.finish(job, result, true);
+                continue;
+            };
+
+            let resolution = job
+                .resolution
+                .take()
+                .filter(|resolution| resolution.applies_to(&conflict))
+                .or_else(|| {
+                    self.resolutions_for_all
+                        .iter()
+                        .find(|resolution| resolution.applies_to(&conflict))
+                        .cloned()
+                })
+                .filter(|resolution| !matches!(resolution, Resolution::Merge) || job.can_merge());
+
+            let Some(resolution) = resolution else {
+                job.conflict = Some(conflict);
+                self.jobs.push_front(job);
+                return self.jobs.front();
+            };
+
+            // A merged directory can't be told apart from what was there, and
+            // an overwritten item is gone for good
+            let can_undo = !matches!(resolution, Resolution::Merge | Resolution::Overwrite);
+
+            let result = match resolution {
+                Resolution::Overwrite => {
+                    restore_item_replacing(&job.entry, &job.target, &mut |bytes| {
+                        progress(&job.entry, bytes)
+                    })
+                }
+                Resolution::KeepBoth => {
+                    job.target = free_path(&job.target);
+                    restore_item_to(&job.entry, &job.target, &mut |bytes| {
+                        progress(&job.entry, bytes)
+                    })
+                }
+                Resolution::Merge => restore_item_merging(&job.entry, &job.target, &mut |bytes| {
+                    progress(&job.entry, bytes)
+                }),
+                Resolution::Skip => {
+                    self.skipped += 1;
+                    continue;
+                }
+                Resolution::Rename(target) => {
+                    job.target = target;
+                    self.jobs.push_front(job);
+                    continue;
+                }
+                Resolution::CreateParents => match job.target.parent().map(fs::create_dir_all) {
+                    Some(Err(e)) => Err(e),
+                    _ => {
+                        self.jobs.push_front(job);
+                        continue;
+                    }
+                },
+                Resolution::RestoreParent => {
+                    if let Conflict::MissingParent(Some(parent)) = conflict {
+                        self.restored_parents.insert(parent.info_path.clone());
+                        // It may be further down the queue already
+                        self.jobs
+                            .retain(|other| other.entry.info_path != parent.info_path);
+                        self.jobs.push_front(job);
+                        self.jobs.push_front(RestoreJob::new(*parent));
+                    }
+                    continue;
+                }
+            };
+
+            self.finish(job, result, can_undo);
+        }
+
+        None
+    }
+
+    fn finish(&mut self, job: RestoreJob, result: Result<(), io::Error>, can_undo: bool) {
+        if result.is_ok() && can_undo {
+            self.restored.push((job.entry.clone(), job.target));
+        }
+
+        self.results.push((job.entry, result));
+    }
+
+    /// The item waiting for a resolution
+    pub fn conflict(&self) -> Option<&RestoreJob> {
+        self.jobs.front()
+    }
+
+    /// Items left after the current one
+    pub fn remaining(&self) -> usize {
+        self.jobs.len().saturating_sub(1)
+    }
+
+    /// Resolves the current conflict, it's applied by the next
+    /// [`RestoreQueue::run`].
+    pub fn resolve(&mut self, resolution: Resolution) {
+        let Some(job) = self.jobs.front_mut() else {
+            return;
+        };
+
+        // A typed name only makes sense for a single item
+        if self.apply_to_all
+            && !matches!(resolution, Resolution::Rename(_))
+            && let Some(conflict) = &job.conflict
+        {
+            self.resolutions_for_all
+                .retain(|other| !other.applies_to(conflict));
+            self.resolutions_for_all.push(resolution.clone());
+        }
+
+        job.resolution = Some(resolution);
+    }
+
+    /// Skips the current item and everything after it.
+    pub fn cancel(&mut self) {
+        self.skipped += self.jobs.len();
+        self.jobs.clear();
+    }
+
+    fn find_conflict(&self, target: &Path) -> Option<Conflict> {
+        // symlink_metadata so that a dangling symlink counts as a conflict
+        if fs::symlink_metadata(target).is_ok() {
+            return Some(Conflict::Exists);
+        }
+
+        match target.parent() {
+            Some(parent) if fs::metadata(parent).is_err() => Some(Conflict::MissingParent(
+                self.trashed_parent(target).map(Box::new),
+            )),
+            _ => None,
+        }
+    }
+
+    // The closest missing ancestor of `target` that's a directory in the
+    // trash, the latest one if it was trashed several times
+    fn trashed_parent(&self, target: &Path) -> Option<TrashEntry> {
+        self.trashed
+            .iter()
+            .filter(|entry| {
+                target.starts_with(&entry.restore_location)
+                    && target != entry.restore_location
+                    && !self.restored_parents.contains(&entry.info_path)
+                    && fs::symlink_metadata(&entry.content_path).is_ok_and(|meta| meta.is_dir())
+                    && fs::symlink_metadata(&entry.restore_location).is_err()
+            })
+            .max_by_key(|entry| (entry.restore_location.components().count(), entry.date))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TempDir, trash_dir, trash_entry, trash_file};
+
+    #[test]
+    fn can_resolve_conflicts() {
+        let root = TempDir::new("restore");
+        let trash_dir = trash_dir(&root.join("trash"));
+        let target_dir = root.join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+
+        fs::write(target_dir.join("a.txt"), "existing").unwrap();
+        fs::write(target_dir.join("b.txt"), "existing").unwrap();
+
+        let mut queue = RestoreQueue::new(
+            vec![
+                trash_file(&trash_dir, "a.txt", &target_dir.join("a.txt")),
+                trash_file(&trash_dir, "b.txt", &target_dir.join("b.txt")),
+                trash_file(&trash_dir, "c.txt", &target_dir.join("c.txt")),
+            ],
+            Vec::new(),
+        );
+
+        assert_eq!(
+            queue.run(&mut |_, _| {}).unwrap().target,
+            target_dir.join("a.txt")
+        );
+        queue.apply_to_all = true;
+        queue.resolve(Resolution::KeepBoth);
+
+        assert!(queue.run(&mut |_, _| {}).is_none());
+        assert_eq!(queue.results.len(), 3);
+        assert!(queue.results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(
+            fs::read_to_string(target_dir.join("a.txt")).unwrap(),
+            "existing"
+        );
+        assert_eq!(
+            fs::read_to_string(target_dir.join("b_2.txt")).unwrap(),
+            "b.txt"
+        );
+        assert!(target_dir.join("c.txt").exists());
+        assert_eq!(queue.restored.len(), 3);
+    }
+
+    #[test]
+    fn cannot_undo_overwrite() {
+        let root = TempDir::new("restore-overwrite");
+        let trash_dir = trash_dir(&root.join("trash"));
+        fs::write(root.join("a.txt"), "existing").unwrap();
+
+        let mut queue = RestoreQueue::new(
+            vec![trash_file(&trash_dir, "a.txt", &root.join("a.txt"))],
+            Vec::new(),
+        );
+        queue.run(&mut |_, _| {}).unwrap();
+        queue.resolve(Resolution::Overwrite);
+
+        assert!(queue.run(&mut |_, _| {}).is_none());
+        assert!(queue.results[0].1.is_ok());
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "a.txt");
+        // Putting it back in the trash wouldn't bring the old file back
+        assert!(queue.restored.is_empty());
+    }
+
+    #[test]
+    fn can_restore_trashed_parent() {
+        let root = TempDir::new("restore-parent");
+        let trash_dir = trash_dir(&root.join("trash"));
+        fs::create_dir_all(trash_dir.files.join("dir")).unwrap();
+        fs::write(trash_dir.info_path_for("dir".as_ref()), "").unwrap();
+
+        let dir = trash_entry(&trash_dir, "dir", &root.join("dir"));
+        let file = trash_file(&trash_dir, "file.txt", &root.join("dir/sub/file.txt"));
+
+        let mut queue = RestoreQueue::new(vec![file], vec![dir]);
+
+        let job = queue.run(&mut |_, _| {}).unwrap();
+        assert!(matches!(
+            job.conflict,
+            Some(Conflict::MissingParent(Some(_)))
+        ));
+        queue.resolve(Resolution::RestoreParent);
+
+        // The parent is back, but not the directory in between
+        let job = queue.run(&mut |_, _| {}).unwrap();
+        assert!(matches!(job.conflict, Some(Conflict::MissingParent(None))));
+        queue.resolve(Resolution::CreateParents);
+
+        assert!(queue.run(&mut |_, _| {}).is_none());
+        assert_eq!(queue.results.len(), 2);
+        assert!(root.join("dir/sub/file.txt").exists());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fs,
+    io::Error,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::MetadataExt,
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+};
+
+use crate::{trash_dir::TrashDir, trash_entry::TrashEntry};
+
+// Example of a `directorysizes` line, one per directory in `files/`:
+// 16384 15803468 Documents%20old
+//
+// The size in bytes as `du -B1` counts it, the modification time of the
+// directory's .trashinfo file in seconds and its percent-encoded name.
+type SizeCache = HashMap<OsString, (u64, i64)>;
+
+/// Content paths of measured directories with their sizes
+pub type SizeReceiver = Receiver<(PathBuf, u64)>;
+
+// Every change to a cache file is a read, modify and write, they must not
+// overlap. The measuring threads and the UI both change them.
+static CACHE_LOCK: Mutex<()> = Mutex::new(());
+static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Size of a trashed item: the disk space used by it and everything inside
+/// it, like `du -B1` counts it. The spec asks for this in `directorysizes`,
+/// files are counted the same way so that all sizes compare.
+pub fn item_size(path: &Path) -> Result<u64, Error> {
+    let meta = fs::symlink_metadata(path)?;
+    let size = meta.blocks() * 512;
+
+    if !meta.is_dir() {
+        return Ok(size);
+    }
+
+    fs::read_dir(path)?.try_fold(size, |size, entry| Ok(size + item_size(&entry?.path())?))
+}
+
+/// Sizes of the items, by content path, as far as they're known right away.
+/// Directories missing from the cache are measured on another thread, their
+/// sizes arrive through the receiver.
+pub fn load_sizes(items: &[TrashEntry]) -> (HashMap<PathBuf, u64>, Option<SizeReceiver>) {
+    let (sizes, missing) = cached_sizes(items);
+
+    if missing.is_empty() {
+        return (sizes, None);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for item in missing {
+            let Some(size) = measure(&item) else {
+                continue;
+            };
+
+            // The list is gone, nobody waits for the rest
+            if sender.send((item.content_path, size)).is_err() {
+                break;
+            }
+        }
+    });
+
+    (sizes, Some(receiver))
+}
+
+/// Like [`load_sizes`], but directories missing from the cache are measured
+/// right away.
+pub fn measure_sizes(items: &[TrashEntry]) -> HashMap<PathBuf, u64> {
+    let (mut sizes, missing) = cached_sizes(items);
+
+    for item in missing {
+        if let Some(size) = measure(&item) {
+            sizes.insert(item.content_path, size);
+        }
+    }
+
+    sizes
+}
+
+// The sizes of files and of cached directories, and the directories that
+// still have to be measured
+fn cached_sizes(items: &[TrashEntry]) -> (HashMap<PathBuf, u64>, Vec<TrashEntry>) {
+    let mut caches = HashMap::new();
+    let mut sizes = HashMap::new();
+    let mut missing = Vec::new();
+
+    for item in items {
+        let Ok(meta) = fs::symlink_metadata(&item.content_path) else {
+            continue;
+        };
+        if !meta.is_dir() {
+            sizes.insert(item.content_path.clone(), meta.blocks() * 512);
+            continue;
+        }
+
+        let cache = caches
+            .entry(item.trash_dir.root.clone())
+            .or_insert_with(|| read_cache(&item.trash_dir));
+        let cached = item
+            .content_path
+            .file_name()
+            .and_then(|name| cache.get(name));
+
+        match (cached, info_mtime(&item.info_path)) {
+            (Some((size, mtime)), Some(info_mtime)) if *mtime == info_mtime => {
+                sizes.insert(item.content_path.clone(), *size);
+            }
+            _ => missing.push(item.clone()),
+        }
+    }
+
+    (sizes, missing)
+}
+
+fn measure(item: &TrashEntry) -> Option<u64> {
+    let size = item_size(&item.content_path).ok()?;
+    remember_size(
+        &item.trash_dir,
+        item.content_path.file_name().unwrap_or_default(),
+        size,
+    );
+
+    Some(size)
+}
+
+/// Adds the directory `name` of `files/` to the cache.
+pub fn remember_size(trash_dir: &TrashDir, name: &OsStr, size: u64) {
+    let Some(mtime) = info_mtime(&trash_dir.info_path_for(name)) else {
+        return;
+    };
+
+    let _lock = CACHE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut cache = read_cache(trash_dir);
+    cache.insert(name.to_os_string(), (size, mtime));
+    write_cache(trash_dir, cache);
+}
+
+/// Drops the cached size of an item that left `files/` or changed. Also
+/// cleans up whatever else is out of date.
+pub fn forget_size(trash_dir: &TrashDir, name: &OsStr) {
+    let _lock = CACHE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut cache = read_cache(trash_dir);
+    if cache.remove(name).is_some() || !cache.is_empty() {
+        write_cache(trash_dir, cache);
+    }
+}
+
+fn info_mtime(info_path: &Path) -> Option<i64> {
+    fs::metadata(info_path).ok().map(|meta| meta.mtime())
+}
+
+fn read_cache(trash_dir: &TrashDir) -> SizeCache {
+    let Ok(content) = fs::read(&trash_dir.directory_sizes) else {
+        return SizeCache::new();
+    };
+
+    content
+        .split(|byte| *byte == b'\n')
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, |byte| *byte == b' ');
+            let size = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+            let mtime = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+            let name = urlencoding::decode_binary(fields.next()?).into_owned();
+
+            Some((OsString::from_vec(name), (size, mtime)))
+        })
+        .collect()
+}
+
+// Entries whose directory is gone are dropped. The file is replaced in one
+// go, as the spec requires, so readers never see half of it. Other processes
+// may be writing it too, every write has a temp file of its own.
+fn write_cache(trash_dir: &TrashDir, mut cache: SizeCache) {
+    cache.retain(|name, _| trash_dir.files.join(name).is_dir());
+
+    let mut lines = cache
+        .into_iter()
+        .map(|(name, (size, mtime))| {
+            format!(
+                "{} {} {}\n",
+                size,
+                mtime,
+                urlencoding::encode_binary(name.as_bytes())
+            )
+        })
+        .collect::<Vec<String>>();
+    lines.sort_by(|a, b| a.split(' ').nth(2).cmp(&b.split(' ').nth(2)));
+
+    let temp = trash_dir.root.join(format!(
+        ".directorysizes.{}.{}",
+        process::id(),
+        NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = fs::write(&temp, lines.concat())
+        .and_then(|_| fs::rename(&temp, &trash_dir.directory_sizes));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TempDir, trash_dir, trash_entry};
+
+    #[test]
+    fn can_cache_directory_sizes() {
+        let root = TempDir::new("sizes");
+        let trash_dir = trash_dir(&root);
+        fs::create_dir_all(trash_dir.files.join("old docs")).unwrap();
+        fs::write(trash_dir.files.join("old docs/a.txt"), "a".repeat(5000)).unwrap();
+        fs::write(trash_dir.info_path_for("old docs".as_ref()), "").unwrap();
+
+        let size = item_size(&trash_dir.files.join("old docs")).unwrap();
+        assert!(size >= 5000);
+
+        remember_size(&trash_dir, "old docs".as_ref(), size);
+        let content = fs::read_to_string(&trash_dir.directory_sizes).unwrap();
+        assert!(content.starts_with(&format!("{} ", size)));
+        assert!(content.ends_with(" old%20docs\n"));
+        assert_eq!(read_cache(&trash_dir)[OsStr::new("old docs")].0, size);
+
+        // Cached sizes are used as they are, files count their blocks too
+        remember_size(&trash_dir, "old docs".as_ref(), 42);
+        let dir = trash_entry(&trash_dir, "old docs", &root.join("old docs"));
+        let file = trash_entry(&trash_dir, "old docs/a.txt", &root.join("a.txt"));
+        let sizes = measure_sizes(&[dir.clone(), file.clone()]);
+        assert_eq!(sizes[&dir.content_path], 42);
+        assert_eq!(
+            sizes[&file.content_path],
+            item_size(&file.content_path).unwrap()
+        );
+        assert_eq!(sizes[&file.content_path] % 512, 0);
+
+        fs::remove_dir_all(trash_dir.files.join("old docs")).unwrap();
+        forget_size(&trash_dir, "old docs".as_ref());
+        assert!(read_cache(&trash_dir).is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use chrono::Local;
+
+use crate::{trash_dir::TrashDir, trash_entry::TrashEntry, trash_info::TrashInfo};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory under the system's temp dir. The name is unique to the
+/// process and the test, it's removed when dropped, even when the test
+/// panics.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!(
+            "trash-tui-test-{}-{}-{}",
+            name,
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A home trash at `root`, with `files/` and `info/` created
+pub fn trash_dir(root: &Path) -> TrashDir {
+    let trash_dir = TrashDir::new(root, None);
+    fs::create_dir_all(&trash_dir.files).unwrap();
+    fs::create_dir_all(&trash_dir.info).unwrap();
+
+    trash_dir
+}
+
+/// The entry `name` of the trash, deleted now. Nothing is written.
+pub fn trash_entry(trash_dir: &TrashDir, name: &str, restore_location: &Path) -> TrashEntry {
+    TrashEntry {
+        display_name: restore_location
+            .file_name()
+            .map_or_else(|| OsString::from(name), |name| name.to_os_string()),
+        info_path: trash_dir.info_path_for(name.as_ref()),
+        content_path: trash_dir.files.join(name),
+        restore_location: restore_location.to_path_buf(),
+        date: Local::now(),
+        trash_dir: trash_dir.clone(),
+    }
+}
+
+/// Like `trash_entry`, but the content (holding `name`) and a valid info
+/// file are written.
+pub fn trash_file(trash_dir: &TrashDir, name: &str, restore_location: &Path) -> TrashEntry {
+    let entry = trash_entry(trash_dir, name, restore_location);
+    fs::write(&entry.content_path, name).unwrap();
+    fs::write(
+        &entry.info_path,
+        TrashInfo {
+            path: entry.restore_location.clone(),
+            deletion_date: entry.date,
+            extra: Vec::new(),
+        }
+        .to_string(),
+    )
+    .unwrap();
+
+    entry
+}
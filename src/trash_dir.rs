@@ -0,0 +1,118 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// A single trash directory as described by the FreeDesktop.org Trash
+/// Specification, e.g. `~/.local/share/Trash` or `/mnt/data/.Trash-1000`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashDir {
+    pub root: PathBuf,
+    pub files: PathBuf,
+    pub info: PathBuf,
+    /// Deleted items wait here until they're purged, so a delete can be
+    /// undone. Not part of the spec, other tools ignore it.
+    pub staging: PathBuf,
+    /// Cached sizes of the directories in `files/`
+    pub directory_sizes: PathBuf,
+    /// Mount point the trash belongs to, `None` for the home trash.
+    pub topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    pub fn new(root: &Path, topdir: Option<&Path>) -> Self {
+        TrashDir {
+            root: root.to_path_buf(),
+            files: root.join("files"),
+            info: root.join("info"),
+            staging: root.join(".staging"),
+            directory_sizes: root.join("directorysizes"),
+            topdir: topdir.map(Path::to_path_buf),
+        }
+    }
+
+    /// Trashinfo files in a top directory trash may store the original
+    /// location relative to the mount point, resolve it to an absolute path.
+    pub fn resolve_path(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_absolute() {
+            return Some(path.to_path_buf());
+        }
+
+        self.topdir.as_ref().map(|topdir| topdir.join(path))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.root.is_dir() && self.files.is_dir() && self.info.is_dir()
+    }
+
+    /// The path stored in `Path=`: relative to the mount point for top
+    /// directory trashes when possible, absolute otherwise.
+    pub fn stored_path(&self, path: &Path) -> PathBuf {
+        self.topdir
+            .as_ref()
+            .and_then(|topdir| path.strip_prefix(topdir).ok())
+            .map_or_else(|| path.to_path_buf(), Path::to_path_buf)
+    }
+
+    pub fn info_path_for(&self, name: &OsStr) -> PathBuf {
+        let mut info_name = name.to_os_string();
+        info_name.push(".trashinfo");
+
+        self.info.join(info_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_resolve_relative_path_against_topdir() {
+        let trash_dir = TrashDir::new(
+            Path::new("/mnt/data/.Trash-1000"),
+            Some(Path::new("/mnt/data")),
+        );
+
+        assert_eq!(
+            trash_dir.resolve_path(Path::new("photos/cat.jpg")),
+            Some(PathBuf::from("/mnt/data/photos/cat.jpg"))
+        );
+        assert_eq!(
+            trash_dir.resolve_path(Path::new("/tmp/cat.jpg")),
+            Some(PathBuf::from("/tmp/cat.jpg"))
+        );
+    }
+
+    #[test]
+    fn cannot_resolve_relative_path_in_home_trash() {
+        let trash_dir = TrashDir::new(Path::new("/home/user/.local/share/Trash"), None);
+
+        assert_eq!(trash_dir.resolve_path(Path::new("photos/cat.jpg")), None);
+        assert_eq!(
+            trash_dir.resolve_path(Path::new("/home/user/cat.jpg")),
+            Some(PathBuf::from("/home/user/cat.jpg"))
+        );
+    }
+
+    #[test]
+    fn can_store_path_relative_to_topdir() {
+        let topdir_trash = TrashDir::new(
+            Path::new("/mnt/data/.Trash-1000"),
+            Some(Path::new("/mnt/data")),
+        );
+        let home_trash = TrashDir::new(Path::new("/home/user/.local/share/Trash"), None);
+
+        assert_eq!(
+            topdir_trash.stored_path(Path::new("/mnt/data/photos/cat.jpg")),
+            PathBuf::from("photos/cat.jpg")
+        );
+        assert_eq!(
+            topdir_trash.stored_path(Path::new("/tmp/cat.jpg")),
+            PathBuf::from("/tmp/cat.jpg")
+        );
+        assert_eq!(
+            home_trash.stored_path(Path::new("/home/user/cat.jpg")),
+            PathBuf::from("/home/user/cat.jpg")
+        );
+    }
+}
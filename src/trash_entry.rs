@@ -1,26 +1,26 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    ffi::OsString,
+    path::{Path, PathBuf},
 };
 
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use chrono::{DateTime, Local};
 
 use crate::{
-    io::{get_trash_dirs, parse_line},
-    ui::Message,
+    trash_dir::TrashDir,
+    trash_info::{TrashInfo, TrashInfoError},
 };
 
 pub struct TrashEntry {
-    pub display_name: String,
+    pub display_name: OsString,
     pub info_path: PathBuf,
     pub content_path: PathBuf,
     pub restore_location: PathBuf,
     pub date: DateTime<Local>,
+    pub trash_dir: TrashDir,
 }
 
 // Example:
-// trash file info path: <trash files dir>/files/video_2.avi
+// trash file info path: <trash files dir>/info/video_2.avi.trashinfo
 // trash file contents path: <trash files dir>/files/video_2.avi
 //
 // Info file:
@@ -29,62 +29,52 @@ pub struct TrashEntry {
 // DeletionDate=2025-07-02T13:40:56
 
 impl TrashEntry {
-    pub fn from_trash_info(path_to_info_file: &PathBuf) -> Result<Self, Message> {
-        let (_, files_dir, _) = get_trash_dirs();
+    pub fn from_trash_info(
+        path_to_info_file: &Path,
+        trash_dir: &TrashDir,
+    ) -> Result<Self, TrashInfoError> {
+        let info = TrashInfo::read(path_to_info_file)?;
 
-        let file = File::open(path_to_info_file)
-            .map_err(|e| Message::error(format!("Error opening trash info file: {}", e)))?;
-        let mut lines = BufReader::new(file).lines();
-        lines.next(); // Skip header
+        let restore_location = trash_dir
+            .resolve_path(&info.path)
+            .ok_or_else(|| TrashInfoError::RelativePath(info.path.clone()))?;
 
-        let restore_location = PathBuf::from(
-            parse_line(&mut lines, path_to_info_file)?
-                .strip_prefix("Path=")
-                .ok_or_else(|| {
-                    Message::error("Missing Path= prefix in restore location".to_string())
-                })?
-                .to_string(),
-        );
-
-        let date = extract_date(parse_line(&mut lines, path_to_info_file)?.as_str())?;
-
-        let file_name = restore_location
+        let display_name = restore_location
             .file_name()
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| Message::error("Invalid or missing file name".to_string()))?;
-
-        let display_name = urlencoding::decode(file_name)
-            .map_err(|e| Message::error(format!("Failed to decode filename: {}", e)))?
-            .to_string();
+            .ok_or(TrashInfoError::MissingFileName)?
+            .to_os_string();
 
         Ok(TrashEntry {
             display_name,
-            info_path: path_to_info_file.clone(),
-            content_path: files_dir.join(
+            info_path: path_to_info_file.to_path_buf(),
+            content_path: trash_dir.files.join(
                 path_to_info_file
                     .file_stem()
-                    .and_then(|s| s.to_str())
-                    .ok_or_else(|| Message::error("Invalid info file name".to_string()))?,
+                    .ok_or(TrashInfoError::InvalidInfoFileName)?,
             ),
             restore_location,
-            date,
+            date: info.deletion_date,
+            trash_dir: trash_dir.clone(),
         })
     }
-}
-
-fn extract_date(date_str: &str) -> Result<DateTime<Local>, Message> {
-    let date_str = date_str
-        .strip_prefix("DeletionDate=")
-        .ok_or_else(|| Message::error("Missing DeletionDate= prefix".to_string()))?
-        .to_string();
 
-    let naive_date = NaiveDateTime::parse_from_str(&date_str, "%Y-%m-%dT%H:%M:%S")
-        .map_err(|_| Message::error("Invalid date format in trash info file".to_string()))?;
+    /// An item inside this trashed directory, `relative` to it. It has no
+    /// info file of its own, `info_path` is the directory's.
+    pub fn child(&self, relative: &Path) -> TrashEntry {
+        TrashEntry {
+            display_name: relative.file_name().unwrap_or_default().to_os_string(),
+            info_path: self.info_path.clone(),
+            content_path: self.content_path.join(relative),
+            restore_location: self.restore_location.join(relative),
+            date: self.date,
+            trash_dir: self.trash_dir.clone(),
+        }
+    }
 
-    Local
-        .from_local_datetime(&naive_date)
-        .single()
-        .ok_or_else(|| Message::error("Ambiguous or invalid local datetime".to_string()))
+    /// Whether this is an item inside a trashed directory
+    pub fn is_child(&self) -> bool {
+        self.content_path.parent() != Some(&self.trash_dir.files)
+    }
 }
 
 impl Clone for TrashEntry {
@@ -94,7 +84,8 @@ impl Clone for TrashEntry {
             info_path: self.info_path.clone(),
             content_path: self.content_path.clone(),
             restore_location: self.restore_location.clone(),
-            date: self.date.clone(),
+            date: self.date,
+            trash_dir: self.trash_dir.clone(),
         }
     }
 }
@@ -0,0 +1,291 @@
+use std::{
+    ffi::OsString,
+    fmt, fs, io,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+const GROUP_HEADER: &str = "[Trash Info]";
+
+/// Contents of a `.trashinfo` file.
+///
+/// The file uses the desktop entry format: the `[Trash Info]` group holds
+/// `Key=Value` pairs in any order, blank lines and `#` comments are allowed.
+pub struct TrashInfo {
+    /// Decoded `Path=` value, relative paths are kept as they are.
+    pub path: PathBuf,
+    pub deletion_date: DateTime<Local>,
+    /// Keys other than `Path` and `DeletionDate`, in file order.
+    pub extra: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum TrashInfoError {
+    Io(io::Error),
+    MissingHeader,
+    MissingKey(&'static str),
+    DuplicateKey(String),
+    InvalidLine(usize),
+    InvalidDate(String),
+    RelativePath(PathBuf),
+    MissingFileName,
+    InvalidInfoFileName,
+}
+
+impl fmt::Display for TrashInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrashInfoError::Io(e) => write!(f, "Error reading trash info file: {}", e),
+            TrashInfoError::MissingHeader => write!(f, "Missing {} group", GROUP_HEADER),
+            TrashInfoError::MissingKey(key) => write!(f, "Missing {}= key", key),
+            TrashInfoError::DuplicateKey(key) => write!(f, "Duplicate {}= key", key),
+            TrashInfoError::InvalidLine(line) => write!(f, "Invalid line {}", line),
+            TrashInfoError::InvalidDate(date) => write!(f, "Invalid deletion date: {}", date),
+            TrashInfoError::RelativePath(path) => {
+                write!(f, "Relative path in the home trash: {}", path.display())
+            }
+            TrashInfoError::MissingFileName => write!(f, "Invalid or missing file name"),
+            TrashInfoError::InvalidInfoFileName => write!(f, "Invalid info file name"),
+        }
+    }
+}
+
+impl std::error::Error for TrashInfoError {}
+
+impl From<io::Error> for TrashInfoError {
+    fn from(e: io::Error) -> Self {
+        TrashInfoError::Io(e)
+    }
+}
+
+impl TrashInfo {
+    pub fn read(path: &Path) -> Result<Self, TrashInfoError> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(content: &[u8]) -> Result<Self, TrashInfoError> {
+        let mut has_header = false;
+        let mut is_in_group = false;
+        let mut path = None;
+        let mut deletion_date = None;
+        let mut extra = Vec::new();
+
+        for (index, line) in content.split(|byte| *byte == b'\n').enumerate() {
+            let line = line.trim_ascii();
+
+            if line.is_empty() || line.starts_with(b"#") {
+                continue;
+            }
+
+            if line.starts_with(b"[") {
+                is_in_group = line == GROUP_HEADER.as_bytes();
+                has_header |= is_in_group;
+                continue;
+            }
+
+            let separator = line
+                .iter()
+                .position(|byte| *byte == b'=')
+                .ok_or(TrashInfoError::InvalidLine(index + 1))?;
+
+            // Keys of other groups are none of our business
+            if !is_in_group {
+                continue;
+            }
+
+            let key = String::from_utf8_lossy(line[..separator].trim_ascii()).to_string();
+            let value = line[separator + 1..].trim_ascii();
+
+            match key.as_str() {
+                "Path" if path.is_some() => return Err(TrashInfoError::DuplicateKey(key)),
+                "Path" => path = Some(decode_path(value)),
+                "DeletionDate" if deletion_date.is_some() => {
+                    return Err(TrashInfoError::DuplicateKey(key));
+                }
+                "DeletionDate" => {
+                    deletion_date = Some(parse_date(&String::from_utf8_lossy(value))?)
+                }
+                _ => extra.push((key, String::from_utf8_lossy(value).to_string())),
+            }
+        }
+
+        if !has_header {
+            return Err(TrashInfoError::MissingHeader);
+        }
+
+        Ok(TrashInfo {
+            path: path.ok_or(TrashInfoError::MissingKey("Path"))?,
+            deletion_date: deletion_date.ok_or(TrashInfoError::MissingKey("DeletionDate"))?,
+            extra,
+        })
+    }
+
+    /// Recovers whatever `Path=` and `DeletionDate=` values are readable from
+    /// a broken file, regardless of groups, order or duplicates.
+    pub fn salvage(content: &[u8], fallback_path: &Path, fallback_date: DateTime<Local>) -> Self {
+        let mut path = None;
+        let mut deletion_date = None;
+
+        for line in content.split(|byte| *byte == b'\n') {
+            let line = line.trim_ascii();
+
+            if let Some(value) = line.strip_prefix(b"Path=") {
+                path = path.or(Some(decode_path(value.trim_ascii())));
+            } else if let Some(value) = line.strip_prefix(b"DeletionDate=") {
+                deletion_date =
+                    deletion_date.or(parse_date(&String::from_utf8_lossy(value.trim_ascii())).ok());
+            }
+        }
+
+        TrashInfo {
+            path: path
+                .filter(|path| path.file_name().is_some())
+                .unwrap_or_else(|| fallback_path.to_path_buf()),
+            deletion_date: deletion_date.unwrap_or(fallback_date),
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for TrashInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", GROUP_HEADER)?;
+        writeln!(f, "Path={}", encode_path(&self.path))?;
+        writeln!(
+            f,
+            "DeletionDate={}",
+            self.deletion_date.format("%Y-%m-%dT%H:%M:%S")
+        )?;
+
+        for (key, value) in &self.extra {
+            writeln!(f, "{}={}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn encode_path(path: &Path) -> String {
+    path.as_os_str()
+        .as_bytes()
+        .split(|byte| *byte == b'/')
+        .map(|component| urlencoding::encode_binary(component).into_owned())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+// The path is percent-encoded byte by byte, so it doesn't have to be valid
+// UTF-8 once decoded (e.g. Latin-1 names).
+pub fn decode_path(encoded: &[u8]) -> PathBuf {
+    PathBuf::from(OsString::from_vec(
+        urlencoding::decode_binary(encoded).into_owned(),
+    ))
+}
+
+// The spec asks for "YYYY-MM-DDThh:mm:ss" in local time, but fractional
+// seconds and timezone offsets show up in files written by other tools.
+fn parse_date(date: &str) -> Result<DateTime<Local>, TrashInfoError> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.with_timezone(&Local));
+    }
+
+    if let Ok(date) = DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Ok(date.with_timezone(&Local));
+    }
+
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .ok_or_else(|| TrashInfoError::InvalidDate(date.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Timelike};
+
+    use super::*;
+
+    #[test]
+    fn can_decode_path() {
+        assert_eq!(
+            decode_path(b"/tmp/%D1%81%D0%B5%D0%B72/video%20clip.avi"),
+            PathBuf::from("/tmp/сез2/video clip.avi")
+        );
+        assert_eq!(
+            decode_path(b"/tmp/caf%E9").as_os_str().as_bytes(),
+            b"/tmp/caf\xe9"
+        );
+    }
+
+    #[test]
+    fn can_write_and_read_back() {
+        let info = TrashInfo::parse(
+            b"[Trash Info]\nPath=/tmp/caf%E9/a%20b.txt\nDeletionDate=2025-07-02T13:40:56\nX-Key=1",
+        )
+        .unwrap();
+        let written = info.to_string();
+
+        assert_eq!(
+            written,
+            "[Trash Info]\nPath=/tmp/caf%E9/a%20b.txt\nDeletionDate=2025-07-02T13:40:56\nX-Key=1\n"
+        );
+        assert_eq!(
+            TrashInfo::parse(written.as_bytes()).unwrap().path,
+            info.path
+        );
+    }
+
+    #[test]
+    fn can_parse_spec_example() {
+        let info = TrashInfo::parse(
+            b"[Trash Info]\nPath=/tmp/video.avi\nDeletionDate=2025-07-02T13:40:56",
+        )
+        .unwrap();
+
+        assert_eq!(info.path, PathBuf::from("/tmp/video.avi"));
+        assert_eq!(info.deletion_date.year(), 2025);
+        assert_eq!(info.deletion_date.second(), 56);
+        assert!(info.extra.is_empty());
+    }
+
+    #[test]
+    fn can_parse_reordered_keys_with_comments() {
+        let info = TrashInfo::parse(
+            b"# written by hand\n\n[Trash Info]\nDeletionDate = 2025-07-02T13:40:56.123+02:00\n\
+              X-Origin=kde\n\nPath=relative/file.txt\n[Other Group]\nPath=/ignored\n",
+        )
+        .unwrap();
+
+        assert_eq!(info.path, PathBuf::from("relative/file.txt"));
+        assert_eq!(
+            info.extra,
+            vec![("X-Origin".to_string(), "kde".to_string())]
+        );
+    }
+
+    #[test]
+    fn can_report_errors() {
+        assert!(matches!(
+            TrashInfo::parse(b"Path=/tmp/a\nDeletionDate=2025-07-02T13:40:56"),
+            Err(TrashInfoError::MissingHeader)
+        ));
+        assert!(matches!(
+            TrashInfo::parse(b"[Trash Info]\nPath=/tmp/a"),
+            Err(TrashInfoError::MissingKey("DeletionDate"))
+        ));
+        assert!(matches!(
+            TrashInfo::parse(b"[Trash Info]\nPath=/tmp/a\nPath=/tmp/b"),
+            Err(TrashInfoError::DuplicateKey(_))
+        ));
+        assert!(matches!(
+            TrashInfo::parse(b"[Trash Info]\nPath=/tmp/a\ngarbage"),
+            Err(TrashInfoError::InvalidLine(3))
+        ));
+        assert!(matches!(
+            TrashInfo::parse(b"[Trash Info]\nPath=/tmp/a\nDeletionDate=yesterday"),
+            Err(TrashInfoError::InvalidDate(_))
+        ));
+    }
+}
@@ -4,13 +4,28 @@ use ratatui::{
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span},
     widgets::{
-        Block, Borders, Clear, Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState,
-        Table, TableState, Wrap,
+        Block, Borders, Clear, Gauge, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Table, TableState, Wrap,
     },
 };
 
-use crate::{list::ListContainer, trash_entry::TrashEntry, utils::Mode};
-use std::{cmp::min, ops::Range};
+use crate::{
+    browser::Browser,
+    diagnostics::DiagnosticsContainer,
+    diff::{Diff, DiffLine},
+    io::Progress,
+    journal::Journal,
+    list::ListContainer,
+    preview::Preview,
+    restore::{Conflict, RestoreJob},
+    trash_entry::TrashEntry,
+    utils::{Mode, format_size},
+};
+use std::{
+    cmp::min,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 const SECONDARY_COLOR: Color = Color::DarkGray;
 const TERTIARY_COLOR: Color = Color::Green;
@@ -51,6 +66,13 @@ pub fn layout(input_mode: &Mode) -> Layout {
     ])
 }
 
+/// Splits the list area, the preview gets the right half when it's shown
+pub fn preview_layout(show_preview: bool) -> Layout {
+    let preview_size = if show_preview { 50 } else { 0 };
+
+    Layout::horizontal([Constraint::Fill(1), Constraint::Percentage(preview_size)])
+}
+
 pub fn render_empty_list(frame: &mut Frame, area: Rect) {
     frame.render_widget(
         Paragraph::new("Trash is empty")
@@ -60,25 +82,224 @@ pub fn render_empty_list(frame: &mut Frame, area: Rect) {
     );
 }
 
-pub fn render_list(frame: &mut Frame, area: Rect, items: Vec<Row>, state: &mut TableState) {
+pub fn render_list(
+    frame: &mut Frame,
+    area: Rect,
+    items: Vec<Row>,
+    state: &mut TableState,
+    selected_location: Option<&Path>,
+    show_location: bool,
+) {
+    let mut block = block_with_border()
+        .title(Span::from("Trash TUI").fg(TEXT_COLOR))
+        .bold();
+
+    if let Some(location) = selected_location {
+        block = block.title_bottom(
+            Line::from(format!(" {} ", location.display()))
+                .style(fg(SECONDARY_COLOR).not_bold())
+                .right_aligned(),
+        );
+    }
+
+    let mut widths = vec![
+        Constraint::Fill(1),
+        Constraint::Length("1023.9 KiB".len() as u16 + 1),
+        Constraint::Length("%Y-%m-%d %H:%M:%S".len() as u16 + 3),
+        // + 3 is for padding.
+    ];
+    if show_location {
+        widths.insert(1, Constraint::Fill(1));
+    }
+
+    frame.render_stateful_widget(
+        Table::new(items, widths)
+            .row_highlight_style(fg(Color::Black).bg(TERTIARY_COLOR).bold())
+            .highlight_symbol(">> ")
+            .block(block),
+        area,
+        state,
+    );
+}
+
+pub fn render_preview(frame: &mut Frame, area: Rect, preview: Option<&Preview>) {
+    let block = block_with_border()
+        .title(Span::from("Preview ").fg(TEXT_COLOR))
+        .bold();
+
+    let lines = match preview {
+        None => vec![Line::from("Nothing selected").fg(SECONDARY_COLOR)],
+        Some(Preview::Text(lines)) if lines.is_empty() => {
+            vec![Line::from("Empty file").fg(SECONDARY_COLOR)]
+        }
+        Some(Preview::Text(lines)) => lines
+            .iter()
+            .map(|line| Line::from(line.as_str()).fg(TEXT_COLOR))
+            .collect(),
+        Some(Preview::Binary(lines)) => lines
+            .iter()
+            .map(|line| Line::from(line.as_str()).fg(TEXT_COLOR).dim())
+            .collect(),
+        Some(Preview::Directory(children, _)) if children.is_empty() => {
+            vec![Line::from("Empty directory").fg(SECONDARY_COLOR)]
+        }
+        Some(Preview::Directory(children, is_truncated)) => {
+            let mut lines = children
+                .iter()
+                .map(|(name, is_dir)| match is_dir {
+                    true => Line::from(format!("{}/", name.to_string_lossy()))
+                        .fg(TERTIARY_COLOR)
+                        .bold(),
+                    false => Line::from(name.to_string_lossy().to_string()).fg(TEXT_COLOR),
+                })
+                .collect::<Vec<_>>();
+            if *is_truncated {
+                lines.push(Line::from("… and more").fg(SECONDARY_COLOR));
+            }
+            lines
+        }
+        Some(Preview::Symlink(target)) => {
+            vec![Line::from(format!("→ {}", target.to_string_lossy())).fg(TEXT_COLOR)]
+        }
+        Some(Preview::Error(e)) => vec![Line::from(e.as_str()).fg(Color::Red)],
+    };
+
+    frame.render_widget(Paragraph::new(lines).not_bold().block(block), area);
+}
+
+pub fn render_browser(frame: &mut Frame, area: Rect, browser: &mut Browser) {
+    let title = browser.entry.display_name.to_string_lossy().to_string()
+        + &match browser.is_at_top() {
+            true => String::new(),
+            false => format!("/{}", browser.dir.to_string_lossy()),
+        };
+
+    let block = block_with_border()
+        .title(Span::from(format!("{} ", title)).fg(TEXT_COLOR))
+        .title_bottom(
+            Line::from(format!(" {} ", browser.location().display()))
+                .style(fg(SECONDARY_COLOR).not_bold())
+                .right_aligned(),
+        )
+        .bold();
+
+    if browser.children.is_empty() {
+        frame.render_widget(
+            Paragraph::new("Empty directory")
+                .block(block)
+                .alignment(Alignment::Center),
+            area,
+        );
+        return;
+    }
+
+    let rows = browser.children.iter().map(|(name, is_dir)| {
+        Row::new(vec![match is_dir {
+            true => Span::from(format!("{}/", name.to_string_lossy()))
+                .fg(TERTIARY_COLOR)
+                .bold(),
+            false => Span::from(name.to_string_lossy().to_string()).fg(TEXT_COLOR),
+        }])
+    });
+
+    frame.render_stateful_widget(
+        Table::new(rows, [Constraint::Fill(1)])
+            .row_highlight_style(fg(Color::Black).bg(TERTIARY_COLOR).bold())
+            .highlight_symbol(">> ")
+            .block(block),
+        area,
+        &mut browser.state,
+    );
+}
+
+pub fn render_diagnostics(frame: &mut Frame, area: Rect, diagnostics: &mut DiagnosticsContainer) {
+    let block = block_with_border()
+        .title(Span::from("Diagnostics").fg(TEXT_COLOR))
+        .bold();
+
+    if diagnostics.items.is_empty() {
+        frame.render_widget(
+            Paragraph::new("No problems found")
+                .block(block)
+                .alignment(Alignment::Center),
+            area,
+        );
+        return;
+    }
+
+    let rows = diagnostics.items.iter().map(|diagnostic| {
+        Row::new(vec![
+            Span::from(diagnostic.label()).fg(Color::Red),
+            Span::from(diagnostic.path.to_string_lossy().to_string()).fg(TEXT_COLOR),
+            Span::from(diagnostic.reason())
+                .fg(TEXT_COLOR)
+                .style(Style::default().dim()),
+        ])
+    });
+
     frame.render_stateful_widget(
         Table::new(
-            items,
+            rows,
             [
+                Constraint::Length("dangling".len() as u16 + 1),
+                Constraint::Fill(2),
                 Constraint::Fill(1),
-                Constraint::Length("%Y-%m-%d %H:%M:%S".len() as u16 + 3),
-                // + 3 is for padding.
             ],
         )
         .row_highlight_style(fg(Color::Black).bg(TERTIARY_COLOR).bold())
         .highlight_symbol(">> ")
-        .block(
-            block_with_border()
-                .title(Span::from("Trash TUI").fg(TEXT_COLOR))
-                .bold(),
-        ),
+        .block(block),
         area,
-        state,
+        &mut diagnostics.state,
+    );
+}
+
+pub fn render_history(frame: &mut Frame, journal: &mut Journal) {
+    let w = min(frame.area().width, 70);
+    let h = min(frame.area().height, 15);
+
+    let x = frame.area().x + (frame.area().width.saturating_sub(w)) / 2;
+    let y = frame.area().y + (frame.area().height.saturating_sub(h)) / 2;
+    let area = Rect::new(x, y, w, h);
+
+    let block = block_with_border()
+        .title(Span::from("History ").fg(TEXT_COLOR))
+        .bold();
+
+    frame.render_widget(Clear, area);
+
+    if journal.records.is_empty() {
+        frame.render_widget(
+            Paragraph::new("Nothing to undo")
+                .block(block)
+                .alignment(Alignment::Center),
+            area,
+        );
+        return;
+    }
+
+    let rows = journal.records.iter().map(|record| {
+        Row::new(vec![
+            Span::from(record.time.format("%H:%M:%S").to_string())
+                .fg(TEXT_COLOR)
+                .style(Style::default().dim()),
+            Span::from(record.description()).fg(TEXT_COLOR),
+        ])
+    });
+
+    frame.render_stateful_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Length("%H:%M:%S".len() as u16 + 1),
+                Constraint::Fill(1),
+            ],
+        )
+        .row_highlight_style(fg(Color::Black).bg(TERTIARY_COLOR).bold())
+        .highlight_symbol(">> ")
+        .block(block),
+        area,
+        &mut journal.state,
     );
 }
 
@@ -92,17 +313,25 @@ pub fn render_scrollbar(frame: &mut Frame, area: Rect, list: &ListContainer) {
         scrollbar,
         area,
         &mut ScrollbarState::default()
-            .content_length(list.items.len())
+            .content_length(list.visible.len())
             .position(list.state.selected().unwrap_or(0)),
     );
 }
 
-pub fn render_search_input(frame: &mut Frame, area: Rect, input: &tui_input::Input) {
-    let input_line = Line::from(vec![
+pub fn render_search_input(
+    frame: &mut Frame,
+    area: Rect,
+    input: &tui_input::Input,
+    error: Option<&str>,
+) {
+    let mut input_line = Line::from(vec![
         Span::from("  ").dim(),
         Span::from(input.value()).bold(),
     ])
     .style(fg(TEXT_COLOR));
+    if let Some(error) = error {
+        input_line.push_span(Span::from(format!("  {}", error)).fg(Color::Red));
+    }
 
     let paragraph = Paragraph::new(input_line)
         .block(block_with_border())
@@ -114,12 +343,66 @@ pub fn render_search_input(frame: &mut Frame, area: Rect, input: &tui_input::Inp
     frame.render_widget(paragraph, area);
 }
 
-pub fn render_footer(frame: &mut Frame, area: Rect, mode: &Mode) {
-    fn special(str: &str) -> Span {
+pub fn render_footer(frame: &mut Frame, area: Rect, mode: &Mode, is_browsing: bool) {
+    fn special(str: &str) -> Span<'_> {
         Span::from(str).style(fg(TERTIARY_COLOR)).bold()
     }
 
     let footer = match mode {
+        Mode::Diagnostics => Line::from(vec![
+            Span::from(" "),
+            special("▲ ▼"),
+            Span::from(" - move, "),
+            special("<q>"),
+            Span::from(" - back, "),
+            special("<f>"),
+            Span::from(" - fix, "),
+            special("<a>"),
+            Span::from(" - adopt, "),
+            special("<d>"),
+            Span::from(" - delete, "),
+            special("<r>"),
+            Span::from(" - rescan"),
+        ]),
+        Mode::History => Line::from(vec![
+            Span::from(" "),
+            special("▲ ▼"),
+            Span::from(" - move, "),
+            special("<u>"),
+            Span::from(" - undo, "),
+            special("<P>"),
+            Span::from(" - purge deleted items, "),
+            special("<q>"),
+            Span::from(" - back"),
+        ]),
+        Mode::PathInput(_) => Line::from(vec![
+            Span::from(" "),
+            special("<enter>"),
+            Span::from(" - confirm, "),
+            special("<tab>"),
+            Span::from(" - complete, "),
+            special("<esc>"),
+            Span::from(" - cancel"),
+        ]),
+        Mode::ListView if is_browsing => Line::from(vec![
+            Span::from(" "),
+            special("▲ ▼"),
+            Span::from(" - move, "),
+            special("◄ ►"),
+            Span::from(" - leave/open directory, "),
+            special("<enter>"),
+            Span::from(" - restore, "),
+            special("<t>"),
+            Span::from(" - restore to, "),
+            special("<x>"),
+            Span::from(" - extract copy, "),
+            special("<p>"),
+            Span::from(" - preview, "),
+            special("<D>"),
+            Span::from(" - diff, "),
+            special("<q>"),
+            Span::from(" - back"),
+        ]),
         Mode::Sorting => Line::from(vec![
             Span::from(" "),
             Span::from("Sort by: "),
@@ -131,6 +414,10 @@ pub fn render_footer(frame: &mut Frame, area: Rect, mode: &Mode) {
             Span::from(" - name, "),
             special("N"),
             Span::from(" - name ascending, "),
+            special("z"),
+            Span::from(" - size, largest first, "),
+            special("Z"),
+            Span::from(" - size ascending, "),
         ]),
         _ => Line::from(vec![
             Span::from(" "),
@@ -140,14 +427,38 @@ pub fn render_footer(frame: &mut Frame, area: Rect, mode: &Mode) {
             Span::from(" - quit, "),
             special("<enter>"),
             Span::from(" - restore, "),
+            special("<t>"),
+            Span::from(" - restore to, "),
+            special("<x>"),
+            Span::from(" - extract copy, "),
             special("<f>"),
             Span::from(" - search, "),
             special("<s>"),
             Span::from(" - sort, "),
             special("<d>"),
             Span::from(" - delete, "),
+            special("<space>"),
+            Span::from(" - mark, "),
+            special("<u>"),
+            Span::from(" - undo, "),
+            special("<H>"),
+            Span::from(" - history, "),
+            special("<p>"),
+            Span::from(" - preview, "),
+            special("<b>"),
+            Span::from(" - browse directory, "),
+            special("<o>"),
+            Span::from(" - location column, "),
+            special("<g>"),
+            Span::from(" - group by directory, "),
+            special("<tab>"),
+            Span::from(" - fold group, "),
+            special("<D>"),
+            Span::from(" - diff with original, "),
             special("<e>"),
-            Span::from(" - empty trash"),
+            Span::from(" - empty trash, "),
+            special("<!>"),
+            Span::from(" - diagnostics"),
         ]),
     };
 
@@ -182,6 +493,205 @@ pub fn render_choice_popup(frame: &mut Frame, question: &str) {
     frame.render_widget(text, area);
 }
 
+pub fn render_conflict_popup(
+    frame: &mut Frame,
+    job: &RestoreJob,
+    apply_to_all: bool,
+    remaining: usize,
+) {
+    fn option<'a>(key: &'a str, label: &'a str) -> Vec<Span<'a>> {
+        vec![
+            Span::from(key).style(fg(TERTIARY_COLOR)).bold(),
+            Span::from(label),
+        ]
+    }
+
+    let w = min(frame.area().width, 80);
+    let h = if remaining > 0 { 8 } else { 7 };
+
+    let x = frame.area().x + (frame.area().width.saturating_sub(w)) / 2;
+    let y = frame.area().y + (frame.area().height.saturating_sub(h)) / 2;
+    let area = Rect::new(x, y, w, h);
+
+    let (message, options) = match &job.conflict {
+        Some(Conflict::MissingParent(parent)) => {
+            let mut options = [option("<c>", " create directories  ")].concat();
+            if parent.is_some() {
+                options.extend(option("<p>", " restore parent  "));
+            }
+            options.extend([option("<r>", " rename  "), option("<s>", " skip")].concat());
+
+            let message = match parent {
+                Some(parent) => format!(
+                    "is in a missing directory, {} is in the trash",
+                    parent.display_name.to_string_lossy()
+                ),
+                None => "is in a missing directory".to_string(),
+            };
+
+            (message, options)
+        }
+        _ => {
+            let mut options = [
+                option("<o>", " overwrite  "),
+                option("<k>", " keep both  "),
+                option("<r>", " rename  "),
+                option("<s>", " skip  "),
+                option("<d>", " diff"),
+            ]
+            .concat();
+            if job.can_merge() {
+                options.extend(option("  <m>", " merge"));
+            }
+
+            ("already exists".to_string(), options)
+        }
+    };
+
+    let mut lines = vec![
+        Line::from(""),
+        Line::from(Span::from(job.target.to_string_lossy().to_string()).bold()),
+        Line::from(message),
+        Line::from(""),
+        Line::from(options),
+    ];
+    if remaining > 0 {
+        lines.push(Line::from(
+            [
+                option("<a>", " apply to all "),
+                vec![Span::from(format!(
+                    "[{}] ({} more)",
+                    if apply_to_all { "x" } else { " " },
+                    remaining
+                ))],
+            ]
+            .concat(),
+        ));
+    }
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(block_with_border().title("Conflict "))
+            .style(fg(TEXT_COLOR))
+            .alignment(Alignment::Center),
+        area,
+    );
+}
+
+pub fn render_diff(frame: &mut Frame, diff: &Diff, scroll: &mut u16) {
+    fn paths<'a>(paths: &'a [PathBuf], prefix: &str, color: Color) -> Vec<Line<'a>> {
+        paths
+            .iter()
+            .map(|path| Line::from(format!("{} {}", prefix, path.to_string_lossy())).fg(color))
+            .collect()
+    }
+
+    let w = frame.area().width * 9 / 10;
+    let h = frame.area().height * 9 / 10;
+
+    let x = frame.area().x + (frame.area().width.saturating_sub(w)) / 2;
+    let y = frame.area().y + (frame.area().height.saturating_sub(h)) / 2;
+    let area = Rect::new(x, y, w, h);
+
+    let lines = match diff {
+        Diff::Text(lines) => lines
+            .iter()
+            .map(|line| match line {
+                DiffLine::Hunk(header) => Line::from(header.as_str()).fg(SECONDARY_COLOR),
+                DiffLine::Added(line) => Line::from(format!("+{}", line)).fg(Color::Green),
+                DiffLine::Removed(line) => Line::from(format!("-{}", line)).fg(Color::Red),
+                DiffLine::Unchanged(line) => Line::from(format!(" {}", line)).fg(TEXT_COLOR),
+            })
+            .collect(),
+        Diff::Directory {
+            added,
+            removed,
+            changed,
+        } => [
+            vec![
+                Line::from(format!(
+                    "{} added, {} removed, {} changed",
+                    added.len(),
+                    removed.len(),
+                    changed.len()
+                ))
+                .fg(TEXT_COLOR)
+                .bold(),
+                Line::from(""),
+            ],
+            paths(added, "+", Color::Green),
+            paths(removed, "-", Color::Red),
+            paths(changed, "~", Color::Yellow),
+        ]
+        .concat(),
+        Diff::Summary(summary) => vec![Line::from(summary.as_str()).fg(TEXT_COLOR)],
+        Diff::Pending(_) => vec![Line::from("Comparing…").fg(TEXT_COLOR)],
+    };
+
+    // Keeps the last line in view, however far it's scrolled
+    *scroll = (*scroll).min(lines.len().saturating_sub(1) as u16);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).scroll((*scroll, 0)).block(
+            block_with_border()
+                .title("Existing → trashed ")
+                .title_bottom(Line::from(" ▲ ▼ scroll, <q> close ").right_aligned()),
+        ),
+        area,
+    );
+}
+
+pub fn render_progress_popup(frame: &mut Frame, name: &str, progress: &Progress) {
+    let w = min(frame.area().width, 60);
+    let h = 4;
+
+    let x = frame.area().x + (frame.area().width.saturating_sub(w)) / 2;
+    let y = frame.area().y + (frame.area().height.saturating_sub(h)) / 2;
+    let area = Rect::new(x, y, w, h);
+
+    let ratio = match progress.total {
+        0 => 1.0,
+        total => (progress.copied as f64 / total as f64).min(1.0),
+    };
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Gauge::default()
+            .block(block_with_border().title(format!("Copying {} ", name)))
+            .gauge_style(fg(TERTIARY_COLOR))
+            .ratio(ratio),
+        area,
+    );
+}
+
+pub fn render_path_input(frame: &mut Frame, title: &str, input: &tui_input::Input) {
+    let w = min(frame.area().width, 80);
+    let h = 3;
+
+    let x = frame.area().x + (frame.area().width.saturating_sub(w)) / 2;
+    let y = frame.area().y + (frame.area().height.saturating_sub(h)) / 2;
+    let area = Rect::new(x, y, w, h);
+
+    // Keep the end of long paths, where the cursor usually is, in view
+    let width = w.saturating_sub(3) as usize;
+    let scroll = input.visual_scroll(width);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(input.value())
+            .scroll((0, scroll as u16))
+            .block(block_with_border().title(format!("{} ", title)))
+            .style(fg(TEXT_COLOR)),
+        area,
+    );
+    frame.set_cursor_position((
+        area.x + 1 + (input.visual_cursor().saturating_sub(scroll)) as u16,
+        area.y + 1,
+    ));
+}
+
 pub fn render_message(frame: &mut Frame, message: &Message) {
     let text = Paragraph::new(message.text.to_string())
         .block(block_with_border())
@@ -213,39 +723,101 @@ pub fn render_message(frame: &mut Frame, message: &Message) {
     frame.render_widget(text, area);
 }
 
-pub fn make_row_widget<'a>(item: &'a TrashEntry, ranges: Option<Vec<Range<usize>>>) -> Row<'a> {
+pub fn make_row_widget(
+    item: &TrashEntry,
+    ranges: Option<Vec<Range<usize>>>,
+    is_marked: bool,
+    size: Option<u64>,
+    location: Option<String>,
+) -> Row<'static> {
+    // Directories may still be being measured
+    let size = Line::from(size.map_or("…".to_string(), format_size))
+        .fg(TEXT_COLOR)
+        .right_aligned();
+
     let date = Span::from(item.date.format("%d-%m-%Y %H:%M:%S").to_string())
         .fg(TEXT_COLOR)
         .style(Style::default().dim());
 
+    // Names aren't necessarily valid UTF-8, they are only converted for display.
+    let name = item.display_name.to_string_lossy();
+
+    let mut characters = vec![match is_marked {
+        true => Span::from("● ").fg(TERTIARY_COLOR),
+        false => Span::from("  "),
+    }];
+
     match ranges {
-        Some(result) if !result.is_empty() => {
-            let mut characters = Vec::new();
-
-            let mut i = 0;
-            for range in result.iter() {
-                characters
-                    .push(Span::from(item.display_name[i..range.start].to_string()).fg(TEXT_COLOR));
-                i = range.end;
-
-                characters.push(
-                    Span::from(item.display_name[range.start..range.end].to_string())
-                        .bold()
-                        .underlined()
-                        .fg(TERTIARY_COLOR),
-                );
-            }
-            characters.push(
-                Span::from(item.display_name[i..item.display_name.len()].to_string())
-                    .fg(TEXT_COLOR),
-            );
+        Some(ranges) if !ranges.is_empty() => {
+            let mut chunk = String::new();
+            let mut is_chunk_highlighted = false;
 
-            Row::new(vec![Line::from(characters), Line::from(date)])
+            // The ranges are byte offsets, so they may end in the middle of a
+            // multi-byte character. Highlight whole characters instead.
+            for (i, character) in name.char_indices() {
+                let is_highlighted = ranges.iter().any(|range| range.contains(&i));
+
+                if is_highlighted != is_chunk_highlighted && !chunk.is_empty() {
+                    characters.push(name_span(std::mem::take(&mut chunk), is_chunk_highlighted));
+                }
+
+                is_chunk_highlighted = is_highlighted;
+                chunk.push(character);
+            }
+            characters.push(name_span(chunk, is_chunk_highlighted));
         }
-        _ => Row::new(vec![
-            Span::from(item.display_name.clone()).fg(TEXT_COLOR),
-            date,
-        ]),
+        _ => characters.push(name_span(name.to_string(), false)),
+    }
+
+    let mut cells = vec![Line::from(characters), size, Line::from(date)];
+    if let Some(location) = location {
+        cells.insert(1, Line::from(location).fg(SECONDARY_COLOR));
+    }
+
+    Row::new(cells)
+}
+
+/// The header of the items trashed from `dir`
+pub fn make_group_row_widget(
+    dir: &str,
+    count: usize,
+    size: Option<u64>,
+    is_expanded: bool,
+    is_marked: bool,
+    show_location: bool,
+) -> Row<'static> {
+    let name = Line::from(vec![
+        match is_marked {
+            true => Span::from("● ").fg(TERTIARY_COLOR),
+            false => Span::from("  "),
+        },
+        Span::from(match is_expanded {
+            true => "▾ ",
+            false => "▸ ",
+        })
+        .fg(TERTIARY_COLOR),
+        Span::from(dir.to_string()).fg(TEXT_COLOR).bold(),
+        Span::from(format!(" ({})", count))
+            .fg(TEXT_COLOR)
+            .style(Style::default().dim()),
+    ]);
+    let size = Line::from(size.map_or("…".to_string(), format_size))
+        .fg(TEXT_COLOR)
+        .bold()
+        .right_aligned();
+
+    let mut cells = vec![name, size, Line::from("")];
+    if show_location {
+        cells.insert(1, Line::from(""));
+    }
+
+    Row::new(cells)
+}
+
+fn name_span<'a>(text: String, is_highlighted: bool) -> Span<'a> {
+    match is_highlighted {
+        true => Span::from(text).bold().underlined().fg(TERTIARY_COLOR),
+        false => Span::from(text).fg(TEXT_COLOR),
     }
 }
 
@@ -1,16 +1,40 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use chrono::TimeDelta;
 use fuse_rust::SearchResult;
 
 use ratatui::{DefaultTerminal, widgets::Row};
 
 use crate::{
+    list::{ListContainer, ListRow, original_dir},
+    query::{ItemState, Query},
     trash_entry::TrashEntry,
-    ui::{layout, make_row_widget},
+    ui::{layout, make_group_row_widget, make_row_widget},
 };
 
 pub enum Mode {
     ListView,
     Filtering,
     Sorting,
+    Diagnostics,
+    PathInput(PathAction),
+    History,
+}
+
+/// What the path typed into the path input is used for
+pub enum PathAction {
+    Adopt,
+    /// Directory to restore the target items into
+    RestoreTo,
+    /// Directory to copy the target items into
+    Extract,
+    /// New name for an item whose original location is taken
+    RenameRestore,
 }
 
 pub enum SortMode {
@@ -18,12 +42,15 @@ pub enum SortMode {
     NameDesc,
     DateAsc,
     DateDesc,
+    SizeAsc,
+    SizeDesc,
 }
 pub enum Choice {
     Restore,
     Delete,
     Empty,
-    Override,
+    DeleteBroken,
+    Purge,
 }
 
 pub fn compute_list_size(terminal: &mut DefaultTerminal) -> usize {
@@ -39,12 +66,162 @@ pub fn compute_list_size(terminal: &mut DefaultTerminal) -> usize {
     s
 }
 
-pub fn with_search<'a>(items: &'a Vec<TrashEntry>, search: &str) -> Vec<Row<'a>> {
-    if search.is_empty() {
-        return items
-            .iter()
-            .map(|item| make_row_widget(&item, None))
-            .collect();
+/// Parses durations like `30d`, `12h`, `2w`, `90m` or `45s`.
+pub fn parse_duration(duration: &str) -> Option<TimeDelta> {
+    let unit_index = duration.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = duration.split_at(unit_index);
+    let amount = amount.parse::<i64>().ok()?;
+
+    match unit {
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// How long deleted items stay staged, `TRASH_TUI_GRACE_PERIOD` (e.g. `12h`)
+/// or a day by default.
+pub fn grace_period() -> TimeDelta {
+    env::var("TRASH_TUI_GRACE_PERIOD")
+        .ok()
+        .and_then(|grace_period| parse_duration(&grace_period))
+        .unwrap_or(TimeDelta::days(1))
+}
+
+/// Human readable size, e.g. `1.5 MiB`
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", size),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(format!("{}{}", home, rest))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Shortens paths in the home directory to `~/...`, the opposite of
+/// [`expand_home`].
+pub fn shorten_home(path: &Path) -> String {
+    match env::var_os("HOME").and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.to_string_lossy()),
+        None => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Completes the last component of a typed path with the directories it
+/// matches, as far as they agree. A single match gets a trailing `/`.
+pub fn complete_dir(path: &str) -> Option<String> {
+    let (dir, prefix) = match path.rfind('/') {
+        Some(index) => path.split_at(index + 1),
+        None => ("", path),
+    };
+
+    let mut matches = fs::read_dir(match dir {
+        "" => PathBuf::from("."),
+        dir => expand_home(dir),
+    })
+    .ok()?
+    .filter_map(|entry| entry.ok())
+    // Follows symlinks, a link to a directory is as good as a directory
+    .filter(|entry| entry.path().is_dir())
+    .filter_map(|entry| entry.file_name().into_string().ok())
+    .filter(|name| name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.')))
+    .collect::<Vec<String>>();
+    matches.sort();
+
+    let (first, last) = (matches.first()?, matches.last()?);
+    if matches.len() == 1 {
+        return Some(format!("{}{}/", dir, first));
+    }
+
+    // The sorted list agrees on whatever its first and last names agree on
+    let common = first
+        .char_indices()
+        .zip(last.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(first.as_str(), |((index, _), _)| &first[..index]);
+
+    Some(format!("{}{}", dir, common))
+}
+
+pub fn visible_rows(list: &ListContainer, show_location: bool) -> Vec<Row<'static>> {
+    list.visible
+        .iter()
+        .map(|row| match row {
+            ListRow::Item(index, ranges) => {
+                let item = &list.items[*index];
+                make_row_widget(
+                    item,
+                    ranges.clone(),
+                    list.marked.contains(&item.info_path),
+                    list.sizes.get(&item.content_path).copied(),
+                    show_location.then(|| shorten_home(original_dir(item))),
+                )
+            }
+            ListRow::Group(dir, indexes) => {
+                let items = indexes.iter().map(|index| &list.items[*index]);
+                // Only known once every item is measured
+                let size = items
+                    .clone()
+                    .map(|item| list.sizes.get(&item.content_path))
+                    .sum::<Option<u64>>();
+
+                make_group_row_widget(
+                    &shorten_home(dir),
+                    indexes.len(),
+                    size,
+                    list.is_expanded(dir),
+                    items
+                        .clone()
+                        .all(|item| list.marked.contains(&item.info_path)),
+                    show_location,
+                )
+            }
+        })
+        .collect()
+}
+
+/// Indexes of the items matching the query, best matches first, along with
+/// the matched ranges of their names.
+pub fn search_items(
+    items: &[TrashEntry],
+    query: &Query,
+    sizes: &HashMap<PathBuf, u64>,
+    item_states: &HashMap<PathBuf, ItemState>,
+) -> Vec<(usize, Option<Vec<Range<usize>>>)> {
+    let matching = (0..items.len())
+        .filter(|index| {
+            let item = &items[*index];
+            query.matches(
+                item,
+                sizes.get(&item.content_path).copied(),
+                item_states.get(&item.content_path),
+            )
+        })
+        .collect::<Vec<usize>>();
+
+    if query.text.is_empty() {
+        return matching.into_iter().map(|index| (index, None)).collect();
     }
 
     let fuse = fuse_rust::Fuse {
@@ -53,15 +230,70 @@ pub fn with_search<'a>(items: &'a Vec<TrashEntry>, search: &str) -> Vec<Row<'a>>
     };
 
     let mut results = fuse
-        .search_text_in_iterable(search, items.iter().map(|item| item.display_name.clone()))
+        .search_text_in_iterable(
+            &query.text,
+            matching
+                .iter()
+                .map(|index| items[*index].display_name.to_string_lossy().to_string()),
+        )
         .into_iter()
         .filter(|result| result.score < 1f64)
         .collect::<Vec<SearchResult>>();
 
     results.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
 
-    return results
+    results
         .into_iter()
-        .map(|result| make_row_widget(&items[result.index], Some(result.ranges)))
-        .collect::<Vec<Row>>();
+        .map(|result| (matching[result.index], Some(result.ranges)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn can_parse_duration() {
+        assert_eq!(parse_duration("45s"), Some(TimeDelta::seconds(45)));
+        assert_eq!(parse_duration("90m"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("12h"), Some(TimeDelta::hours(12)));
+        assert_eq!(parse_duration("30d"), Some(TimeDelta::days(30)));
+        assert_eq!(parse_duration("2w"), Some(TimeDelta::weeks(2)));
+        assert_eq!(parse_duration("0d"), Some(TimeDelta::zero()));
+
+        for invalid in [
+            "",
+            "30",
+            "d",
+            "7y",
+            "1.5d",
+            "-1d",
+            "30 d",
+            "30D",
+            "99999999999999w",
+        ] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn can_complete_dir() {
+        let root = TempDir::new("complete");
+        for dir in ["alpha", "alps", "beta", ".alhidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("alpine.txt"), "").unwrap();
+        let root = root.to_string_lossy();
+
+        assert_eq!(
+            complete_dir(&format!("{}/al", root)),
+            Some(format!("{}/alp", root))
+        );
+        assert_eq!(
+            complete_dir(&format!("{}/alph", root)),
+            Some(format!("{}/alpha/", root))
+        );
+        assert_eq!(complete_dir(&format!("{}/x", root)), None);
+    }
 }